@@ -4,6 +4,7 @@ mod prompts;
 use crate::prompts::*;
 use anyhow::Result;
 use clap::Parser;
+use dygma_api::devices::DeviceState;
 use dygma_api::flash::devices::defy;
 use dygma_focus::prelude::*;
 use tracing::{debug, error, info};
@@ -50,7 +51,10 @@ async fn main() -> Result<()> {
     let cli = Cli::parse();
 
     let device = if !cli.debug.unwrap_or(false) {
-        let devices = Focus::find_all_devices()?;
+        let devices = dygma_api::devices::find_all_devices_grouped()?
+            .into_values()
+            .flatten()
+            .collect::<Vec<_>>();
         match devices.len() {
             0 => {
                 error!("No devices found, please connect a device and try again");
@@ -95,9 +99,28 @@ async fn main() -> Result<()> {
         return Ok(());
     }
 
+    if let DeviceState::Application { version } = dygma_api::devices::probe(&device).await? {
+        for warning in dygma_api::flash::flash_warnings(&version, &firmware_release) {
+            if !ask_accept_warning(&warning)? {
+                info!("Flash cancelled");
+                return Ok(());
+            }
+        }
+    }
+
+    dygma_api::flash::check_firmware_target(&device.hardware, &firmware_release, &firmwares)?;
+
     // Testing `Defy flash`
     if let Some(hex_raw) = firmwares.firmware.hex_raw {
-        let mut flasher = defy::nrf52833_flasher::Flasher::new(&device)?;
+        let mut flasher =
+            defy::nrf52833_flasher::Flasher::new(&device)?.with_progress(|progress| {
+                info!(
+                    "Flashed {}/{} bytes, ~{}s remaining",
+                    progress.written,
+                    progress.total,
+                    progress.eta.map(|eta| eta.as_secs()).unwrap_or(0)
+                )
+            });
         flasher.flash(&hex_raw).await?;
     }
 
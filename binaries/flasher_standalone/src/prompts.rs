@@ -1,6 +1,6 @@
 use anyhow::Result;
 use dygma_api::firmware_downloader::FirmwareRelease;
-use dygma_focus::hardware::types::hardware_physical;
+use dygma_api::flash::FlashWarning;
 use dygma_focus::hardware::{Device, Hardware};
 use inquire::{Confirm, Select};
 
@@ -18,11 +18,9 @@ pub fn ask_connected_device(options: Vec<Device>) -> Result<Device> {
 }
 
 pub fn ask_hardware() -> Result<Hardware> {
-    Ok(
-        Select::new("Device?", hardware_physical::DEVICES_PHYSICAL.to_vec())
-            .with_help_message("Select the product")
-            .prompt()?,
-    )
+    Ok(Select::new("Device?", dygma_api::devices::all().to_vec())
+        .with_help_message("Select the product")
+        .prompt()?)
 }
 
 pub fn ask_firmware(
@@ -45,3 +43,10 @@ pub fn ask_firmware(
             .prompt()?)
     }
 }
+
+pub fn ask_accept_warning(warning: &FlashWarning) -> Result<bool> {
+    Ok(Confirm::new(&format!("{}, continue?", warning))
+        .with_default(false)
+        .with_help_message("Confirm you want to flash this firmware")
+        .prompt()?)
+}
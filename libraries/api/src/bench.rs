@@ -0,0 +1,71 @@
+use crate::command::command_response;
+use crate::flash::devices::defy::nrf52833_flasher::Flasher;
+use anyhow::Result;
+use dygma_focus::Focus;
+use serde::Serialize;
+use std::time::{Duration, Instant};
+
+/// Throughput measurement, serializable so runs can be compared across changes.
+#[derive(Debug, Clone, Serialize)]
+pub struct BenchResult {
+    pub name: String,
+    pub iterations: usize,
+    pub elapsed: Duration,
+    pub operations_per_second: f64,
+    pub bytes: usize,
+    pub bytes_per_second: f64,
+}
+
+impl BenchResult {
+    fn new(name: &str, iterations: usize, bytes: usize, elapsed: Duration) -> Self {
+        let seconds = elapsed.as_secs_f64().max(f64::EPSILON);
+
+        Self {
+            name: name.to_string(),
+            iterations,
+            elapsed,
+            operations_per_second: iterations as f64 / seconds,
+            bytes,
+            bytes_per_second: bytes as f64 / seconds,
+        }
+    }
+}
+
+/// Round trips of a command per second.
+pub async fn commands_per_second(
+    focus: &mut Focus,
+    command: &str,
+    iterations: usize,
+) -> Result<BenchResult> {
+    let mut bytes = 0;
+    let start = Instant::now();
+
+    for _ in 0..iterations {
+        bytes += command_response(focus, command).await?.len();
+    }
+
+    Ok(BenchResult::new(
+        command,
+        iterations,
+        bytes,
+        start.elapsed(),
+    ))
+}
+
+/// Response bytes per second, using `keymap.custom` as a large response.
+pub async fn bytes_per_second(focus: &mut Focus, iterations: usize) -> Result<BenchResult> {
+    commands_per_second(focus, "keymap.custom", iterations).await
+}
+
+/// Flash transfer rate of a full firmware write.
+pub async fn flash_transfer_rate(flasher: &mut Flasher, file_content: &str) -> Result<BenchResult> {
+    let bytes = Flasher::ihex_decode_lines(file_content)?
+        .iter()
+        .map(|hex| hex.data.len())
+        .sum();
+
+    let start = Instant::now();
+    flasher.flash(file_content).await?;
+
+    Ok(BenchResult::new("flash", 1, bytes, start.elapsed()))
+}
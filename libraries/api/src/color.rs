@@ -0,0 +1,727 @@
+use crate::command::command_response;
+use anyhow::{bail, Context, Result};
+use dygma_focus::color::{RGB, RGBW};
+use dygma_focus::hardware::Hardware;
+use dygma_focus::Focus;
+use log::{debug, info, warn};
+use serde::Deserialize;
+use serde_json::Value;
+use std::path::Path;
+use tokio::fs;
+
+pub const PALETTE_SIZE: usize = 16;
+
+#[derive(Debug, Clone)]
+pub enum ThemePalette {
+    Rgb(Vec<RGB>),
+    Rgbw(Vec<RGBW>),
+}
+
+impl ThemePalette {
+    pub fn len(&self) -> usize {
+        match self {
+            ThemePalette::Rgb(colors) => colors.len(),
+            ThemePalette::Rgbw(colors) => colors.len(),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+/// Palette being edited, capped at the device's palette size.
+#[derive(Debug, Clone)]
+pub struct Palette {
+    colors: Vec<RGB>,
+    capacity: usize,
+}
+
+impl Palette {
+    pub fn with_capacity(capacity: usize) -> Self {
+        Self {
+            colors: Vec::with_capacity(capacity),
+            capacity,
+        }
+    }
+
+    pub fn from_colors(colors: Vec<RGB>, capacity: usize) -> Result<Self> {
+        if colors.len() > capacity {
+            bail!(
+                "Palette has {} colors, the device only holds {}",
+                colors.len(),
+                capacity
+            );
+        }
+
+        Ok(Self { colors, capacity })
+    }
+
+    pub fn colors(&self) -> &[RGB] {
+        &self.colors
+    }
+
+    pub fn len(&self) -> usize {
+        self.colors.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.colors.is_empty()
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    pub fn is_full(&self) -> bool {
+        self.colors.len() >= self.capacity
+    }
+
+    pub fn index_of(&self, color: &RGB) -> Option<u8> {
+        self.colors
+            .iter()
+            .position(|c| c.r == color.r && c.g == color.g && c.b == color.b)
+            .map(|index| index as u8)
+    }
+
+    /// Returns the slot holding the color, adding it if it isn't in the palette yet.
+    pub fn add(&mut self, color: &RGB) -> Result<u8> {
+        if let Some(index) = self.index_of(color) {
+            return Ok(index);
+        }
+
+        if self.is_full() {
+            bail!("Palette is full ({} colors)", self.capacity);
+        }
+
+        self.colors.push(RGB {
+            r: color.r,
+            g: color.g,
+            b: color.b,
+        });
+
+        Ok((self.colors.len() - 1) as u8)
+    }
+}
+
+impl Default for Palette {
+    fn default() -> Self {
+        Self::with_capacity(PALETTE_SIZE)
+    }
+}
+
+impl From<Palette> for ThemePalette {
+    fn from(palette: Palette) -> Self {
+        ThemePalette::Rgb(palette.colors)
+    }
+}
+
+impl ThemePalette {
+    /// Color of a palette slot as displayed, with the white channel blended in for RGBW.
+    pub fn display_rgb(&self, index: u8) -> Option<(u8, u8, u8)> {
+        match self {
+            ThemePalette::Rgb(colors) => colors.get(index as usize).map(|c| (c.r, c.g, c.b)),
+            ThemePalette::Rgbw(colors) => colors.get(index as usize).map(|c| {
+                (
+                    c.r.saturating_add(c.w),
+                    c.g.saturating_add(c.w),
+                    c.b.saturating_add(c.w),
+                )
+            }),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Theme {
+    pub palette: ThemePalette,
+    pub colormap: Vec<u8>,
+}
+
+#[derive(Debug, Deserialize)]
+struct BazecorColor {
+    r: u8,
+    g: u8,
+    b: u8,
+    w: Option<u8>,
+}
+
+#[derive(Debug, Deserialize)]
+struct BazecorBackupEntry {
+    command: String,
+    data: String,
+}
+
+impl Theme {
+    /// Reads the palette and colormap from a Bazecor JSON export.
+    ///
+    /// Supports both layer exports (`palette` and `colormap` at the top level)
+    /// and full backups (`backup` list of `palette` and `colormap.map` commands).
+    pub async fn from_bazecor_file<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let path = path.as_ref();
+        info!("Reading Bazecor theme from: {:?}", path);
+
+        let json = fs::read_to_string(path).await?;
+        let value: Value = serde_json::from_str(&json)?;
+
+        if let Some(backup) = value.get("backup") {
+            return Self::from_bazecor_backup(backup.clone());
+        }
+
+        let palette = value
+            .get("palette")
+            .context("Bazecor file does not contain a palette")?;
+        let colors: Vec<BazecorColor> = serde_json::from_value(palette.clone())?;
+
+        let colormap = value
+            .get("colormap")
+            .context("Bazecor file does not contain a colormap")?;
+        let colormap = match colormap {
+            Value::Array(items) if items.iter().all(Value::is_array) => {
+                serde_json::from_value::<Vec<Vec<u8>>>(colormap.clone())?
+                    .into_iter()
+                    .flatten()
+                    .collect()
+            }
+            _ => serde_json::from_value::<Vec<u8>>(colormap.clone())?,
+        };
+
+        Ok(Self {
+            palette: Self::palette_from_colors(colors),
+            colormap,
+        })
+    }
+
+    /// Samples an image at each LED position of a single layer.
+    ///
+    /// The image is stretched over the keyboard grid and again over the underglow grid,
+    /// and the sampled colors are reduced to a device sized palette.
+    pub fn from_image<P: AsRef<Path>>(path: P, hardware: &Hardware) -> Result<Self> {
+        let image = image::open(path.as_ref())?.to_rgb8();
+        let (width, height) = image.dimensions();
+
+        let mut samples = Vec::with_capacity(leds_per_layer(hardware));
+        for grid in [&hardware.keyboard, &hardware.keyboard_underglow]
+            .into_iter()
+            .flatten()
+        {
+            for row in 0..grid.rows as u32 {
+                for col in 0..grid.columns as u32 {
+                    let x = (2 * col + 1) * width / (2 * grid.columns as u32);
+                    let y = (2 * row + 1) * height / (2 * grid.rows as u32);
+                    let [r, g, b] = image.get_pixel(x, y).0;
+                    samples.push(RGB { r, g, b });
+                }
+            }
+        }
+
+        let (palette, colormap) = quantize(&samples, PALETTE_SIZE);
+
+        Ok(Self {
+            palette: palette.into(),
+            colormap,
+        })
+    }
+
+    fn from_bazecor_backup(backup: Value) -> Result<Self> {
+        let entries: Vec<BazecorBackupEntry> = serde_json::from_value(backup)?;
+
+        let find = |command: &str| {
+            entries
+                .iter()
+                .find(|entry| entry.command == command)
+                .map(|entry| parse_numbers(&entry.data))
+                .with_context(|| format!("Bazecor backup does not contain '{}'", command))
+        };
+
+        let palette = find("palette")??;
+        let colormap = find("colormap.map")??;
+
+        Ok(Self {
+            palette: Self::palette_from_values(&palette)?,
+            colormap,
+        })
+    }
+
+    fn palette_from_colors(colors: Vec<BazecorColor>) -> ThemePalette {
+        if colors.iter().any(|color| color.w.is_some()) {
+            ThemePalette::Rgbw(
+                colors
+                    .into_iter()
+                    .map(|color| RGBW {
+                        r: color.r,
+                        g: color.g,
+                        b: color.b,
+                        w: color.w.unwrap_or_default(),
+                    })
+                    .collect(),
+            )
+        } else {
+            ThemePalette::Rgb(
+                colors
+                    .into_iter()
+                    .map(|color| RGB {
+                        r: color.r,
+                        g: color.g,
+                        b: color.b,
+                    })
+                    .collect(),
+            )
+        }
+    }
+
+    fn palette_from_values(values: &[u8]) -> Result<ThemePalette> {
+        match values.len() {
+            n if n == PALETTE_SIZE * 4 => Ok(ThemePalette::Rgbw(
+                values
+                    .chunks_exact(4)
+                    .map(|c| RGBW {
+                        r: c[0],
+                        g: c[1],
+                        b: c[2],
+                        w: c[3],
+                    })
+                    .collect(),
+            )),
+            n if n == PALETTE_SIZE * 3 => Ok(ThemePalette::Rgb(
+                values
+                    .chunks_exact(3)
+                    .map(|c| RGB {
+                        r: c[0],
+                        g: c[1],
+                        b: c[2],
+                    })
+                    .collect(),
+            )),
+            n => bail!("Palette length {} is not RGB or RGBW", n),
+        }
+    }
+}
+
+fn color_distance(a: &RGB, b: &RGB) -> u32 {
+    let channel = |a: u8, b: u8| (a as i32 - b as i32).pow(2) as u32;
+    channel(a.r, b.r) + channel(a.g, b.g) + channel(a.b, b.b)
+}
+
+/// Reduces colors to a palette of at most `capacity` entries, returning the palette and the
+/// index of each color within it.
+///
+/// Exact colors are kept until the palette is full, the rest use the nearest palette color.
+pub fn quantize(colors: &[RGB], capacity: usize) -> (Palette, Vec<u8>) {
+    let mut palette = Palette::with_capacity(capacity);
+
+    let indices = colors
+        .iter()
+        .map(|color| {
+            palette.add(color).unwrap_or_else(|_| {
+                palette
+                    .colors()
+                    .iter()
+                    .enumerate()
+                    .min_by_key(|(_, c)| color_distance(c, color))
+                    .map(|(index, _)| index as u8)
+                    .unwrap_or_default()
+            })
+        })
+        .collect();
+
+    (palette, indices)
+}
+
+fn parse_numbers(data: &str) -> Result<Vec<u8>> {
+    data.split_whitespace()
+        .map(|part| part.parse::<u8>().map_err(Into::into))
+        .collect()
+}
+
+/// Determines the palette format from the live `palette` response.
+///
+/// Falls back to `Hardware::rgbw_mode` when the response length doesn't identify the format,
+/// and warns when the live format disagrees with the static hint. Errors when neither the
+/// response nor the hardware definition identifies the format.
+#[tracing::instrument(skip(focus, hardware))]
+pub async fn is_rgbw(focus: &mut Focus, hardware: &Hardware) -> Result<bool> {
+    let response = command_response(focus, "palette").await?;
+    let components = response.split_whitespace().count();
+
+    let live = match components {
+        n if n == PALETTE_SIZE * 4 => true,
+        n if n == PALETTE_SIZE * 3 => false,
+        n => {
+            let Some(hint) = hardware.rgbw_mode else {
+                bail!(
+                    "Palette has {} components and {} has no RGBW hint, can't determine the palette format",
+                    n,
+                    hardware.info.display_name
+                );
+            };
+            warn!(
+                "Palette has {} components, using the hardware definition (RGBW: {})",
+                n, hint
+            );
+            return Ok(hint);
+        }
+    };
+
+    match hardware.rgbw_mode {
+        Some(hint) if hint != live => warn!(
+            "{} reports RGBW: {}, but the hardware definition says {}",
+            hardware.info.display_name, live, hint
+        ),
+        None => debug!(
+            "{} has no RGBW hint, the palette reports RGBW: {}",
+            hardware.info.display_name, live
+        ),
+        _ => {}
+    }
+
+    Ok(live)
+}
+
+/// Reads the palette in the format the firmware uses.
+pub async fn palette_get(focus: &mut Focus, hardware: &Hardware) -> Result<ThemePalette> {
+    if is_rgbw(focus, hardware).await? {
+        Ok(ThemePalette::Rgbw(focus.palette_rgbw_get().await?))
+    } else {
+        Ok(ThemePalette::Rgb(focus.palette_rgb_get().await?))
+    }
+}
+
+pub async fn palette_set(focus: &mut Focus, palette: &ThemePalette) -> Result<()> {
+    match palette {
+        ThemePalette::Rgb(colors) => focus.palette_rgb_set(colors).await?,
+        ThemePalette::Rgbw(colors) => focus.palette_rgbw_set(colors).await?,
+    }
+
+    Ok(())
+}
+
+/// Writes the palette and then the colormap of a theme to the device.
+#[tracing::instrument(skip(focus, theme))]
+pub async fn apply_theme(focus: &mut Focus, theme: &Theme) -> Result<()> {
+    set_palette_and_colormap(focus, &theme.palette, &theme.colormap).await
+}
+
+/// Checks that every colormap entry references an existing palette slot.
+pub fn validate_colormap(palette_len: usize, colormap: &[u8]) -> Result<()> {
+    if let Some((position, index)) = colormap
+        .iter()
+        .enumerate()
+        .find(|(_, &index)| index as usize >= palette_len)
+    {
+        bail!(
+            "Colormap entry {} references palette index {}, but the palette only has {} colors",
+            position,
+            index,
+            palette_len
+        );
+    }
+
+    Ok(())
+}
+
+/// Validates the colormap against the palette, then writes the palette followed by the colormap.
+///
+/// Nothing is sent to the device if any colormap index is out of range.
+#[tracing::instrument(skip(focus, palette, colormap))]
+pub async fn set_palette_and_colormap(
+    focus: &mut Focus,
+    palette: &ThemePalette,
+    colormap: &[u8],
+) -> Result<()> {
+    validate_colormap(palette.len(), colormap)?;
+
+    palette_set(focus, palette).await?;
+    focus.color_map_set(colormap).await?;
+
+    Ok(())
+}
+
+/// Number of LEDs in a single colormap layer, keys followed by underglow.
+pub fn leds_per_layer(hardware: &Hardware) -> usize {
+    [&hardware.keyboard, &hardware.keyboard_underglow]
+        .into_iter()
+        .flatten()
+        .map(|grid| grid.rows as usize * grid.columns as usize)
+        .sum()
+}
+
+/// Replaces the colors of a single layer, leaving the other layers untouched.
+#[tracing::instrument(skip(focus, hardware, indices))]
+pub async fn colormap_layer_set(
+    focus: &mut Focus,
+    hardware: &Hardware,
+    layer: u8,
+    indices: &[u8],
+) -> Result<()> {
+    let layer_size = leds_per_layer(hardware);
+    if indices.len() != layer_size {
+        bail!(
+            "Layer colormap has {} entries, expected {}",
+            indices.len(),
+            layer_size
+        );
+    }
+
+    let mut colormap = focus.color_map_get().await?;
+    let start = layer as usize * layer_size;
+    if start + layer_size > colormap.len() {
+        bail!("Layer {} is out of range", layer);
+    }
+
+    colormap[start..start + layer_size].copy_from_slice(indices);
+    focus.color_map_set(&colormap).await?;
+
+    Ok(())
+}
+
+/// Position and length of the underglow LEDs within a colormap layer.
+fn underglow_range(hardware: &Hardware) -> Result<std::ops::Range<usize>> {
+    let underglow = hardware
+        .keyboard_underglow
+        .as_ref()
+        .context("Hardware has no underglow grid")?;
+    let keys = hardware
+        .keyboard
+        .as_ref()
+        .map(|grid| grid.rows as usize * grid.columns as usize)
+        .unwrap_or(0);
+
+    Ok(keys..keys + underglow.rows as usize * underglow.columns as usize)
+}
+
+/// Reads the underglow palette indices of a single layer.
+#[tracing::instrument(skip(focus, hardware))]
+pub async fn colormap_underglow_get(
+    focus: &mut Focus,
+    hardware: &Hardware,
+    layer: u8,
+) -> Result<Vec<u8>> {
+    let range = underglow_range(hardware)?;
+    let start = layer as usize * leds_per_layer(hardware);
+
+    let colormap = focus.color_map_get().await?;
+    let Some(underglow) = colormap.get(start + range.start..start + range.end) else {
+        bail!("Layer {} is out of range", layer);
+    };
+
+    Ok(underglow.to_vec())
+}
+
+/// Replaces the underglow palette indices of a single layer, leaving the key colors untouched.
+#[tracing::instrument(skip(focus, hardware, indices))]
+pub async fn colormap_underglow_set(
+    focus: &mut Focus,
+    hardware: &Hardware,
+    layer: u8,
+    indices: &[u8],
+) -> Result<()> {
+    let range = underglow_range(hardware)?;
+    if indices.len() != range.len() {
+        bail!(
+            "Underglow colormap has {} entries, expected {}",
+            indices.len(),
+            range.len()
+        );
+    }
+
+    let mut colormap = focus.color_map_get().await?;
+    let start = layer as usize * leds_per_layer(hardware);
+    let Some(underglow) = colormap.get_mut(start + range.start..start + range.end) else {
+        bail!("Layer {} is out of range", layer);
+    };
+
+    underglow.copy_from_slice(indices);
+    focus.color_map_set(&colormap).await?;
+
+    Ok(())
+}
+
+/// Sets the palette index of a single LED on a layer.
+#[tracing::instrument(skip(focus, hardware))]
+pub async fn colormap_led_set(
+    focus: &mut Focus,
+    hardware: &Hardware,
+    layer: u8,
+    led: usize,
+    index: u8,
+) -> Result<()> {
+    let layer_size = leds_per_layer(hardware);
+    if led >= layer_size {
+        bail!(
+            "LED {} is out of range, the layer has {} LEDs",
+            led,
+            layer_size
+        );
+    }
+
+    let mut colormap = focus.color_map_get().await?;
+    let position = layer as usize * layer_size + led;
+    if position >= colormap.len() {
+        bail!("Layer {} is out of range", layer);
+    }
+
+    colormap[position] = index;
+    focus.color_map_set(&colormap).await?;
+
+    Ok(())
+}
+
+/// Renders each layer's colors as ANSI truecolor blocks laid out on the device grids.
+pub fn render_preview(palette: &ThemePalette, colormap: &[u8], hardware: &Hardware) -> String {
+    let layer_size = leds_per_layer(hardware);
+    if layer_size == 0 {
+        return String::new();
+    }
+
+    let mut output = String::new();
+
+    for (layer, colors) in colormap.chunks(layer_size).enumerate() {
+        output.push_str(&format!("Layer {}\n", layer));
+
+        let mut offset = 0;
+        for grid in [&hardware.keyboard, &hardware.keyboard_underglow]
+            .into_iter()
+            .flatten()
+        {
+            for row in 0..grid.rows as usize {
+                for col in 0..grid.columns as usize {
+                    let position = offset + row * grid.columns as usize + col;
+                    let block = colors
+                        .get(position)
+                        .and_then(|&index| palette.display_rgb(index))
+                        .map(|(r, g, b)| format!("\x1b[38;2;{};{};{}m██\x1b[0m", r, g, b))
+                        .unwrap_or_else(|| "??".to_string());
+                    output.push_str(&block);
+                }
+                output.push('\n');
+            }
+            offset += grid.rows as usize * grid.columns as usize;
+            output.push('\n');
+        }
+    }
+
+    output
+}
+
+struct ColorSnapshot {
+    palette: ThemePalette,
+    colormap: Vec<u8>,
+    theme: Vec<RGB>,
+}
+
+async fn write_colors(
+    focus: &mut Focus,
+    palette: &ThemePalette,
+    colormap: &[u8],
+    theme: Option<&[RGB]>,
+) -> Result<()> {
+    set_palette_and_colormap(focus, palette, colormap).await?;
+
+    if let Some(theme) = theme {
+        focus.led_theme_set(theme).await?;
+    }
+
+    Ok(())
+}
+
+/// Writes palette, colormap and optionally the LED theme, restoring the previous values if
+/// any write fails.
+#[tracing::instrument(skip(focus, hardware, palette, colormap, theme))]
+pub async fn apply_colors_atomic(
+    focus: &mut Focus,
+    hardware: &Hardware,
+    palette: &ThemePalette,
+    colormap: &[u8],
+    theme: Option<&[RGB]>,
+) -> Result<()> {
+    validate_colormap(palette.len(), colormap)?;
+
+    let snapshot = ColorSnapshot {
+        palette: palette_get(focus, hardware).await?,
+        colormap: focus.color_map_get().await?,
+        theme: focus.led_theme_get().await?,
+    };
+
+    let Err(e) = write_colors(focus, palette, colormap, theme).await else {
+        return Ok(());
+    };
+
+    warn!("Applying colors failed, restoring previous colors: {}", e);
+
+    let restore = write_colors(
+        focus,
+        &snapshot.palette,
+        &snapshot.colormap,
+        Some(&snapshot.theme),
+    )
+    .await;
+
+    match restore {
+        Ok(()) => Err(e.context("Applying colors failed, previous colors restored")),
+        Err(restore_error) => bail!(
+            "Applying colors failed: {}, restoring previous colors also failed: {}",
+            e,
+            restore_error
+        ),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn validate_colormap_rejects_out_of_range_index() {
+        let error = validate_colormap(16, &[0, 15, 16, 3]).unwrap_err();
+
+        assert_eq!(
+            error.to_string(),
+            "Colormap entry 2 references palette index 16, but the palette only has 16 colors"
+        );
+    }
+
+    #[test]
+    fn validate_colormap_accepts_in_range_indices() {
+        assert!(validate_colormap(16, &[0, 7, 15]).is_ok());
+    }
+
+    fn rgb(r: u8, g: u8, b: u8) -> RGB {
+        RGB { r, g, b }
+    }
+
+    #[test]
+    fn palette_add_reuses_existing_slots() {
+        let mut palette = Palette::with_capacity(4);
+
+        assert_eq!(palette.add(&rgb(255, 0, 0)).unwrap(), 0);
+        assert_eq!(palette.add(&rgb(0, 255, 0)).unwrap(), 1);
+        assert_eq!(palette.add(&rgb(255, 0, 0)).unwrap(), 0);
+        assert_eq!(palette.len(), 2);
+        assert_eq!(palette.index_of(&rgb(0, 255, 0)), Some(1));
+        assert_eq!(palette.index_of(&rgb(0, 0, 255)), None);
+    }
+
+    #[test]
+    fn palette_add_fails_when_full() {
+        let mut palette = Palette::with_capacity(1);
+
+        palette.add(&rgb(1, 2, 3)).unwrap();
+
+        assert!(palette.is_full());
+        assert!(palette.add(&rgb(4, 5, 6)).is_err());
+        assert_eq!(palette.add(&rgb(1, 2, 3)).unwrap(), 0);
+    }
+
+    #[test]
+    fn palette_from_colors_respects_capacity() {
+        assert!(Palette::from_colors(vec![rgb(0, 0, 0); 3], 2).is_err());
+        assert_eq!(
+            Palette::from_colors(vec![rgb(0, 0, 0); 2], 2)
+                .unwrap()
+                .len(),
+            2
+        );
+        assert_eq!(Palette::default().capacity(), PALETTE_SIZE);
+    }
+}
@@ -0,0 +1,14 @@
+use anyhow::Result;
+use dygma_focus::Focus;
+
+/// Sends a raw Focus command and returns its trimmed response.
+///
+/// For commands without a typed getter in `dygma_focus`. An empty response is an ack.
+#[tracing::instrument(skip(focus))]
+pub async fn command_response(focus: &mut Focus, command: &str) -> Result<String> {
+    focus
+        .write_bytes(format!("{}\n", command).as_bytes())
+        .await?;
+
+    focus.read_string().await
+}
@@ -0,0 +1,736 @@
+use anyhow::{bail, Result};
+use dygma_focus::hardware::types::hardware_physical::DEVICES_PHYSICAL;
+use dygma_focus::hardware::{Device, Grid, Hardware};
+use dygma_focus::Focus;
+use futures::future::BoxFuture;
+use log::{debug, info, warn};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fmt::Display;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+use tokio_serial::{SerialPortInfo, SerialPortType};
+
+static REGISTERED: Mutex<Vec<Hardware>> = Mutex::new(Vec::new());
+/// Serial ports of the devices currently being flashed.
+static FLASHING: Mutex<Vec<String>> = Mutex::new(Vec::new());
+
+#[derive(Debug)]
+pub enum DiscoveryError {
+    /// The operating system could not list serial ports.
+    EnumerationFailed(String),
+    /// A Dygma port exists but the current user cannot open it.
+    PermissionDenied { port: String },
+}
+
+impl Display for DiscoveryError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            DiscoveryError::EnumerationFailed(e) => {
+                write!(f, "Failed to enumerate serial ports: {}", e)
+            }
+            DiscoveryError::PermissionDenied { port } => write!(
+                f,
+                "Permission denied opening {}, add your user to the serial group (e.g. `sudo usermod -aG dialout $USER`) and log in again",
+                port
+            ),
+        }
+    }
+}
+
+impl std::error::Error for DiscoveryError {}
+
+/// The device can't take normal commands right now.
+#[derive(Debug)]
+pub enum DeviceBusy {
+    Flashing { port: String },
+    Bootloader { port: String },
+}
+
+impl Display for DeviceBusy {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            DeviceBusy::Flashing { port } => write!(f, "Device on {} is being flashed", port),
+            DeviceBusy::Bootloader { port } => {
+                write!(f, "Device on {} is in bootloader mode", port)
+            }
+        }
+    }
+}
+
+impl std::error::Error for DeviceBusy {}
+
+/// Whether a flash is in progress on the port.
+pub fn is_flashing(port: &str) -> bool {
+    FLASHING
+        .lock()
+        .unwrap()
+        .iter()
+        .any(|flashing| flashing == port)
+}
+
+fn ensure_not_flashing(port: &str) -> Result<(), DeviceBusy> {
+    if is_flashing(port) {
+        return Err(DeviceBusy::Flashing {
+            port: port.to_string(),
+        });
+    }
+
+    Ok(())
+}
+
+/// Refuses devices that are being flashed or are in bootloader mode.
+pub fn ensure_idle(device: &Device) -> Result<(), DeviceBusy> {
+    ensure_not_flashing(&device.serial_port)?;
+    if device.hardware.bootloader {
+        return Err(DeviceBusy::Bootloader {
+            port: device.serial_port.clone(),
+        });
+    }
+
+    Ok(())
+}
+
+/// An open Focus connection that remembers its port.
+///
+/// `Focus` doesn't expose the port it was opened on, so the helpers taking `&mut Focus`
+/// get it through [`Connection::focus`], which refuses while the device is being flashed.
+#[derive(Debug)]
+pub struct Connection {
+    focus: Focus,
+    port: String,
+}
+
+impl Connection {
+    /// Opens the device, refusing when it is being flashed or is in bootloader mode.
+    pub fn open(device: &Device) -> Result<Self> {
+        ensure_idle(device)?;
+
+        Ok(Self {
+            focus: Focus::new_via_device(device)?,
+            port: device.serial_port.clone(),
+        })
+    }
+
+    pub fn port(&self) -> &str {
+        &self.port
+    }
+
+    /// The underlying connection, unless a flash has started on the port since it was opened.
+    pub fn focus(&mut self) -> Result<&mut Focus, DeviceBusy> {
+        ensure_not_flashing(&self.port)?;
+
+        Ok(&mut self.focus)
+    }
+}
+
+/// Marks a device as being flashed until dropped.
+#[derive(Debug)]
+pub struct FlashingGuard {
+    port: String,
+}
+
+impl FlashingGuard {
+    /// Claims the device for flashing, refusing when another flash is already running on it.
+    pub fn acquire(device: &Device) -> Result<Self, DeviceBusy> {
+        let mut flashing = FLASHING.lock().unwrap();
+        let port = device.serial_port.clone();
+        if flashing.contains(&port) {
+            return Err(DeviceBusy::Flashing { port });
+        }
+
+        flashing.push(port.clone());
+
+        Ok(Self { port })
+    }
+}
+
+impl Drop for FlashingGuard {
+    fn drop(&mut self) {
+        FLASHING.lock().unwrap().retain(|port| port != &self.port);
+    }
+}
+
+/// What can be learned about the firmware of a connected device.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DeviceState {
+    Application {
+        version: String,
+    },
+    /// The bootloader doesn't answer Focus commands, so the installed version is unknown.
+    Bootloader,
+}
+
+/// Determines whether the device is running its application firmware, and which version.
+#[tracing::instrument(skip(device))]
+pub async fn probe(device: &Device) -> Result<DeviceState> {
+    if device.hardware.bootloader {
+        return Ok(DeviceState::Bootloader);
+    }
+    let mut connection = Connection::open(device)?;
+    let version = connection.focus()?.version().await?;
+
+    Ok(DeviceState::Application { version })
+}
+
+const READY_RETRY_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Waits until the Neuron answers `version` with a plausible response.
+///
+/// Right after the port is opened the first commands can time out or return garbage. Each
+/// attempt is cut off at the deadline, so a command that never answers can't outlast `timeout`.
+#[tracing::instrument(skip(focus))]
+pub async fn wait_ready(focus: &mut Focus, timeout: Duration) -> Result<String> {
+    wait_until_ready(focus, timeout, |focus| Box::pin(focus.version())).await
+}
+
+async fn wait_until_ready<S>(
+    source: &mut S,
+    timeout: Duration,
+    mut version: impl FnMut(&mut S) -> BoxFuture<'_, Result<String>>,
+) -> Result<String> {
+    let deadline = Instant::now() + timeout;
+
+    loop {
+        let remaining = deadline.saturating_duration_since(Instant::now());
+        match tokio::time::timeout(remaining, version(source)).await {
+            Ok(Ok(version)) if is_plausible_version(&version) => return Ok(version),
+            Ok(Ok(version)) => debug!("Device not ready, got: {:?}", version),
+            Ok(Err(e)) => debug!("Device not ready: {}", e),
+            Err(_) => debug!("Device didn't answer within {:?}", remaining),
+        }
+
+        let remaining = deadline.saturating_duration_since(Instant::now());
+        if remaining.is_zero() {
+            bail!("Device not ready after {:?}", timeout);
+        }
+
+        tokio::time::sleep(READY_RETRY_INTERVAL.min(remaining)).await;
+    }
+}
+
+fn is_plausible_version(version: &str) -> bool {
+    let version = version.trim().trim_start_matches('v');
+    version.starts_with(|c: char| c.is_ascii_digit())
+        && version.chars().all(|c| c.is_ascii_graphic())
+}
+
+/// Opens the device and waits for it to finish booting.
+///
+/// Returns [`DeviceBusy`] while the device is being flashed or is in bootloader mode.
+pub async fn connect_ready(device: &Device, timeout: Duration) -> Result<Connection> {
+    let mut connection = Connection::open(device)?;
+    wait_ready(connection.focus()?, timeout).await?;
+
+    Ok(connection)
+}
+
+/// Every built-in hardware definition, including bootloader variants.
+pub fn all() -> &'static [Hardware] {
+    DEVICES_PHYSICAL.as_slice()
+}
+
+const BOOTLOADER_SUFFIX: &str = " (Bootloader)";
+
+/// The bootloader definition a device reboots into for flashing.
+///
+/// Matched on the display name rather than the product, since the Defy Wired and Wireless
+/// share a product but have separate bootloaders, and the Raise ANSI and ISO share USB IDs.
+pub fn bootloader_variant(hardware: &Hardware) -> Option<&'static Hardware> {
+    if hardware.bootloader {
+        return None;
+    }
+
+    let name = format!("{}{}", hardware.info.display_name, BOOTLOADER_SUFFIX);
+    all()
+        .iter()
+        .find(|candidate| candidate.bootloader && candidate.info.display_name == name)
+}
+
+/// The application definition of a bootloader, the reverse of [`bootloader_variant`].
+pub fn application_variant(hardware: &Hardware) -> Option<&'static Hardware> {
+    let name = hardware.info.display_name.strip_suffix(BOOTLOADER_SUFFIX)?;
+
+    all()
+        .iter()
+        .find(|candidate| !candidate.bootloader && candidate.info.display_name == name)
+}
+
+/// Built-in hardware definitions excluding bootloader variants.
+pub fn all_application() -> Vec<&'static Hardware> {
+    all()
+        .iter()
+        .filter(|hardware| !hardware.bootloader)
+        .collect()
+}
+
+/// Teaches discovery about a device that isn't in the built-in hardware tables.
+///
+/// Registered devices are matched by USB vendor and product ID, registering the same IDs
+/// again replaces the earlier definition.
+pub fn register_device(hardware: Hardware) {
+    info!(
+        "Registering device: {} [{:04x}:{:04x}]",
+        hardware.info.display_name, hardware.usb.vendor_id, hardware.usb.product_id
+    );
+
+    let mut registered = REGISTERED.lock().unwrap();
+    registered.retain(|existing| {
+        let duplicate = existing.usb.vendor_id == hardware.usb.vendor_id
+            && existing.usb.product_id == hardware.usb.product_id;
+        if duplicate {
+            debug!(
+                "Replacing registered device: {}",
+                existing.info.display_name
+            );
+        }
+        !duplicate
+    });
+    registered.push(hardware);
+}
+
+/// All devices added through [`register_device`].
+pub fn registered_devices() -> Vec<Hardware> {
+    REGISTERED.lock().unwrap().clone()
+}
+
+/// Hardware definitions matching a USB port, built-in ones taking precedence over registered.
+///
+/// Every built-in match is returned, since the Raise ANSI and ISO share USB IDs.
+fn hardware_for(vid: u16, pid: u16, registered: &[Hardware]) -> Vec<Hardware> {
+    let matches =
+        |hardware: &&Hardware| hardware.usb.vendor_id == vid && hardware.usb.product_id == pid;
+
+    let built_in = DEVICES_PHYSICAL
+        .iter()
+        .filter(matches)
+        .copied()
+        .collect::<Vec<_>>();
+    if !built_in.is_empty() {
+        return built_in;
+    }
+
+    registered.iter().filter(matches).take(1).copied().collect()
+}
+
+/// Finds connected devices, consulting both the built-in and registered hardware.
+///
+/// An empty list means no ports matched, [`DiscoveryError`] is returned
+/// when ports couldn't be enumerated or a Dygma port couldn't be opened.
+pub fn find_all_devices() -> Result<Vec<Device>> {
+    let ports = tokio_serial::available_ports()
+        .map_err(|e| DiscoveryError::EnumerationFailed(e.to_string()))?;
+    let registered = registered_devices();
+
+    let mut devices = Vec::new();
+    for port in &ports {
+        let SerialPortType::UsbPort(usb) = &port.port_type else {
+            continue;
+        };
+
+        for hardware in hardware_for(usb.vid, usb.pid, &registered) {
+            debug!(
+                "Found device: {} [{}]",
+                hardware.info.display_name, port.port_name
+            );
+            devices.push(Device {
+                hardware,
+                serial_port: port.port_name.clone(),
+            });
+        }
+    }
+
+    check_permissions(&ports)?;
+
+    Ok(devices)
+}
+
+/// Async variant of [`find_all_devices`], enumerating ports on the blocking thread pool.
+///
+/// Port enumeration blocks, so call this from async code to keep the executor responsive.
+/// [`find_all_devices`] remains the choice for synchronous callers.
+pub async fn find_all_devices_async() -> Result<Vec<Device>> {
+    tokio::task::spawn_blocking(find_all_devices).await?
+}
+
+const DISCOVERY_INITIAL_BACKOFF: Duration = Duration::from_millis(100);
+const DISCOVERY_MAX_BACKOFF: Duration = Duration::from_secs(1);
+
+/// Polls discovery until a device appears, for connecting right after the keyboard is plugged in.
+///
+/// Some USB stacks take a second or two to list a new port. The poll interval starts short
+/// and doubles up to a second. Errors other than an empty result are returned immediately.
+#[tracing::instrument]
+pub async fn find_first_device_wait(timeout: Duration) -> Result<Device> {
+    wait_for_device(timeout, find_all_devices_async).await
+}
+
+async fn wait_for_device<F, Fut>(timeout: Duration, mut discover: F) -> Result<Device>
+where
+    F: FnMut() -> Fut,
+    Fut: std::future::Future<Output = Result<Vec<Device>>>,
+{
+    let start = Instant::now();
+    let mut backoff = DISCOVERY_INITIAL_BACKOFF;
+
+    loop {
+        if let Some(device) = discover().await?.into_iter().next() {
+            return Ok(device);
+        }
+
+        let elapsed = start.elapsed();
+        if elapsed >= timeout {
+            bail!("No device found after {:?}", timeout);
+        }
+
+        debug!("No device yet, retrying in {:?}", backoff);
+        tokio::time::sleep(backoff.min(timeout - elapsed)).await;
+        backoff = (backoff * 2).min(DISCOVERY_MAX_BACKOFF);
+    }
+}
+
+/// Connected devices grouped by product name, with products and ports in a stable order.
+///
+/// Intended for apps presenting their own device picker.
+pub fn find_all_devices_grouped() -> Result<BTreeMap<String, Vec<Device>>> {
+    let mut grouped: BTreeMap<String, Vec<Device>> = BTreeMap::new();
+
+    for device in find_all_devices()? {
+        grouped
+            .entry(device.hardware.info.product.to_string())
+            .or_default()
+            .push(device);
+    }
+
+    for devices in grouped.values_mut() {
+        devices.sort_by(|a, b| a.serial_port.cmp(&b.serial_port));
+    }
+
+    Ok(grouped)
+}
+
+fn is_known_vendor(port: &SerialPortInfo) -> bool {
+    let SerialPortType::UsbPort(usb) = &port.port_type else {
+        return false;
+    };
+
+    DEVICES_PHYSICAL
+        .iter()
+        .chain(registered_devices().iter())
+        .any(|hardware| hardware.usb.vendor_id == usb.vid)
+}
+
+/// Detects the common first-run problem of the user lacking access to the serial device.
+///
+/// Every port with a Dygma vendor ID is opened, including those already matched to a device,
+/// since a matched port is just as unusable when it can't be opened.
+#[cfg(unix)]
+fn check_permissions(ports: &[SerialPortInfo]) -> Result<(), DiscoveryError> {
+    for port in ports.iter().filter(|port| is_known_vendor(port)) {
+        if let Err(e) = std::fs::OpenOptions::new()
+            .read(true)
+            .write(true)
+            .open(&port.port_name)
+        {
+            if e.kind() == std::io::ErrorKind::PermissionDenied {
+                warn!("Permission denied: {}", port.port_name);
+                return Err(DiscoveryError::PermissionDenied {
+                    port: port.port_name.clone(),
+                });
+            }
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(not(unix))]
+fn check_permissions(_ports: &[SerialPortInfo]) -> Result<(), DiscoveryError> {
+    Ok(())
+}
+
+/// An enumerated serial port and the hardware it was matched to, if any.
+#[derive(Debug, Clone)]
+pub struct PortInfo {
+    pub port_name: String,
+    pub port_type: String,
+    pub vid: Option<u16>,
+    pub pid: Option<u16>,
+    pub matched: Option<Hardware>,
+}
+
+/// Lists every serial port, including those that aren't Dygma devices.
+///
+/// Useful for working out why a keyboard isn't detected, such as an unexpected VID/PID.
+pub fn list_ports_verbose() -> Result<Vec<PortInfo>> {
+    let ports = tokio_serial::available_ports()
+        .map_err(|e| DiscoveryError::EnumerationFailed(e.to_string()))?;
+
+    let known = DEVICES_PHYSICAL
+        .iter()
+        .cloned()
+        .chain(registered_devices())
+        .collect::<Vec<_>>();
+
+    Ok(ports
+        .into_iter()
+        .map(|port| {
+            let (port_type, vid, pid) = match &port.port_type {
+                SerialPortType::UsbPort(usb) => ("USB", Some(usb.vid), Some(usb.pid)),
+                SerialPortType::PciPort => ("PCI", None, None),
+                SerialPortType::BluetoothPort => ("Bluetooth", None, None),
+                SerialPortType::Unknown => ("Unknown", None, None),
+            };
+
+            let matched = known
+                .iter()
+                .find(|hardware| {
+                    Some(hardware.usb.vendor_id) == vid && Some(hardware.usb.product_id) == pid
+                })
+                .cloned();
+
+            PortInfo {
+                port_name: port.port_name,
+                port_type: port_type.to_string(),
+                vid,
+                pid,
+                matched,
+            }
+        })
+        .collect())
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct GridDescriptor {
+    pub rows: u8,
+    pub columns: u8,
+}
+
+/// Owned, serializable view of a device, suitable for persisting the selected device.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DeviceDescriptor {
+    pub product: String,
+    pub device_type: String,
+    pub display_name: String,
+    pub vendor_id: u16,
+    pub product_id: u16,
+    pub bootloader: bool,
+    /// `None` when the hardware definition doesn't state the palette format.
+    pub rgbw_mode: Option<bool>,
+    pub keyboard: Option<GridDescriptor>,
+    pub keyboard_underglow: Option<GridDescriptor>,
+    pub serial_port: Option<String>,
+}
+
+impl DeviceDescriptor {
+    /// Whether the descriptor was created from this hardware definition.
+    pub fn matches(&self, hardware: &Hardware) -> bool {
+        self.vendor_id == hardware.usb.vendor_id
+            && self.product_id == hardware.usb.product_id
+            && self.bootloader == hardware.bootloader
+    }
+}
+
+impl From<&Hardware> for DeviceDescriptor {
+    fn from(hardware: &Hardware) -> Self {
+        let grid = |grid: &Option<Grid>| {
+            grid.as_ref().map(|grid| GridDescriptor {
+                rows: grid.rows,
+                columns: grid.columns,
+            })
+        };
+
+        Self {
+            product: hardware.info.product.to_string(),
+            device_type: format!("{:?}", hardware.info.device_type),
+            display_name: hardware.info.display_name.to_string(),
+            vendor_id: hardware.usb.vendor_id,
+            product_id: hardware.usb.product_id,
+            bootloader: hardware.bootloader,
+            rgbw_mode: hardware.rgbw_mode,
+            keyboard: grid(&hardware.keyboard),
+            keyboard_underglow: grid(&hardware.keyboard_underglow),
+            serial_port: None,
+        }
+    }
+}
+
+impl From<&Device> for DeviceDescriptor {
+    fn from(device: &Device) -> Self {
+        Self {
+            serial_port: Some(device.serial_port.clone()),
+            ..Self::from(&device.hardware)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use dygma_focus::hardware::types::hardware_physical::{
+        DEFY_WIRED, DEFY_WIRELESS, DEFY_WIRELESS_BOOTLOADER, RAISE_ISO,
+    };
+
+    #[test]
+    fn built_in_hardware_wins_over_registered() {
+        let mut custom = DEFY_WIRED;
+        custom.info.display_name = "Custom Defy";
+
+        let matched = hardware_for(
+            DEFY_WIRED.usb.vendor_id,
+            DEFY_WIRED.usb.product_id,
+            &[custom],
+        );
+        assert_eq!(matched.len(), 1);
+        assert_eq!(matched[0].info.display_name, DEFY_WIRED.info.display_name);
+    }
+
+    #[test]
+    fn unknown_ports_match_registered_hardware() {
+        let mut custom = DEFY_WIRED;
+        custom.usb.vendor_id = 0x1234;
+        custom.usb.product_id = 0x5678;
+
+        assert_eq!(hardware_for(0x1234, 0x5678, &[custom]).len(), 1);
+        assert!(hardware_for(0x1234, 0x9999, &[custom]).is_empty());
+    }
+
+    #[test]
+    fn registering_the_same_ids_replaces_the_definition() {
+        let mut first = DEFY_WIRED;
+        first.usb.vendor_id = 0xfffe;
+        first.usb.product_id = 0xfffd;
+        first.info.display_name = "First";
+        let mut second = first;
+        second.info.display_name = "Second";
+
+        register_device(first);
+        register_device(second);
+
+        let registered = registered_devices()
+            .into_iter()
+            .filter(|hardware| hardware.usb.vendor_id == 0xfffe)
+            .collect::<Vec<_>>();
+        assert_eq!(registered.len(), 1);
+        assert_eq!(registered[0].info.display_name, "Second");
+    }
+
+    #[test]
+    fn flashing_guard_blocks_the_port_until_dropped() {
+        let device = Device {
+            hardware: DEFY_WIRED,
+            serial_port: "flashing-guard-test".to_string(),
+        };
+
+        let guard = FlashingGuard::acquire(&device).unwrap();
+        assert!(matches!(
+            ensure_idle(&device),
+            Err(DeviceBusy::Flashing { .. })
+        ));
+        assert!(ensure_not_flashing("another-port").is_ok());
+
+        drop(guard);
+        assert!(ensure_idle(&device).is_ok());
+    }
+
+    #[test]
+    fn all_application_excludes_bootloaders() {
+        assert_eq!(all().len(), DEVICES_PHYSICAL.len());
+        assert!(all_application()
+            .iter()
+            .all(|hardware| !hardware.bootloader));
+        assert_eq!(all_application().len() * 2, all().len());
+    }
+
+    #[test]
+    fn bootloader_variant_keeps_wired_and_wireless_apart() {
+        let wireless = bootloader_variant(&DEFY_WIRELESS).unwrap();
+        let wired = bootloader_variant(&DEFY_WIRED).unwrap();
+
+        assert_eq!(
+            wireless.info.display_name,
+            DEFY_WIRELESS_BOOTLOADER.info.display_name
+        );
+        assert_eq!(
+            wireless.usb.product_id,
+            DEFY_WIRELESS_BOOTLOADER.usb.product_id
+        );
+        assert_ne!(wired.usb.product_id, wireless.usb.product_id);
+    }
+
+    #[test]
+    fn variants_round_trip() {
+        for hardware in all_application() {
+            let bootloader = bootloader_variant(hardware).unwrap();
+            let application = application_variant(bootloader).unwrap();
+
+            assert_eq!(application.info.display_name, hardware.info.display_name);
+        }
+
+        assert!(bootloader_variant(bootloader_variant(&RAISE_ISO).unwrap()).is_none());
+    }
+
+    #[tokio::test]
+    async fn wait_for_device_polls_until_a_device_appears() {
+        let mut polls = 0;
+        let device = wait_for_device(Duration::from_secs(5), || {
+            polls += 1;
+            let devices = if polls < 3 {
+                Vec::new()
+            } else {
+                vec![Device {
+                    hardware: DEFY_WIRED,
+                    serial_port: "test".to_string(),
+                }]
+            };
+            async move { Ok(devices) }
+        })
+        .await
+        .unwrap();
+
+        assert_eq!(polls, 3);
+        assert_eq!(device.serial_port, "test");
+    }
+
+    #[tokio::test]
+    async fn wait_for_device_times_out() {
+        let result = wait_for_device(Duration::from_millis(150), || async { Ok(Vec::new()) }).await;
+
+        assert!(result.is_err());
+    }
+
+    #[tokio::test]
+    async fn wait_ready_accepts_a_delayed_response() {
+        let mut attempts = 0;
+        let version = wait_until_ready(&mut attempts, Duration::from_secs(5), |attempts| {
+            *attempts += 1;
+            let attempt = *attempts;
+            Box::pin(async move {
+                match attempt {
+                    1 => Ok("\u{0}garbage".to_string()),
+                    _ => {
+                        tokio::time::sleep(Duration::from_millis(50)).await;
+                        Ok("v1.2.3".to_string())
+                    }
+                }
+            })
+        })
+        .await
+        .unwrap();
+
+        assert_eq!(version, "v1.2.3");
+        assert_eq!(attempts, 2);
+    }
+
+    #[tokio::test]
+    async fn wait_ready_gives_up_on_a_command_that_never_answers() {
+        let start = Instant::now();
+        let result = wait_until_ready(&mut (), Duration::from_millis(150), |_| {
+            Box::pin(std::future::pending())
+        })
+        .await;
+
+        assert!(result.is_err());
+        assert!(start.elapsed() < Duration::from_secs(1));
+    }
+}
@@ -0,0 +1,207 @@
+use crate::command::command_response;
+use crate::features::{help_detailed, supports};
+use anyhow::{bail, Context, Result};
+use dygma_focus::Focus;
+use log::{debug, warn};
+use std::collections::BTreeMap;
+use std::time::Duration;
+
+/// Commands that only read state when sent without arguments.
+///
+/// Taken from the firmware `help` tables and the getters dygma_focus sends. Anything else,
+/// including actions such as `wireless.bluetooth.remove`, is never sent by [`dump_all`].
+const READ_ONLY_COMMANDS: &[&str] = &[
+    "version",
+    "keymap.custom",
+    "keymap.default",
+    "keymap.onlyCustom",
+    "settings.defaultLayer",
+    "settings.valid?",
+    "settings.version",
+    "settings.crc",
+    "eeprom.contents",
+    "eeprom.free",
+    "led.mode",
+    "led.brightness",
+    "led.brightnessUG",
+    "led.brightness.wireless",
+    "led.brightnessUG.wireless",
+    "led.fade",
+    "led.theme",
+    "palette",
+    "colormap.map",
+    "idleleds.time_limit",
+    "idleleds.true_sleep",
+    "idleleds.true_sleep_time",
+    "idleleds.wireless",
+    "hardware.version",
+    "hardware.side_power",
+    "hardware.side_ver",
+    "hardware.sled_ver",
+    "hardware.sled_current",
+    "hardware.layout",
+    "hardware.joint",
+    "hardware.keyscan",
+    "hardware.crc_errors",
+    "hardware.firmware",
+    "hardware.chip_id",
+    "qukeys.holdTimeout",
+    "qukeys.overlapThreshold",
+    "superkeys.map",
+    "superkeys.waitfor",
+    "superkeys.timeout",
+    "superkeys.repeat",
+    "superkeys.holdstart",
+    "superkeys.overlap",
+    "macros.map",
+    "macros.memory",
+    "mouse.speed",
+    "mouse.speedDelay",
+    "mouse.accelSpeed",
+    "mouse.accelDelay",
+    "mouse.wheelSpeed",
+    "mouse.wheelDelay",
+    "mouse.speedLimit",
+    "layer.state",
+    "wireless.battery.left.level",
+    "wireless.battery.right.level",
+    "wireless.battery.left.status",
+    "wireless.battery.right.status",
+    "wireless.battery.savingMode",
+    "wireless.rf.power",
+    "wireless.rf.channelHop",
+    "wireless.bluetooth.list",
+    "wireless.bluetooth.currentHost",
+];
+
+fn is_read_only(command: &str) -> bool {
+    READ_ONLY_COMMANDS.contains(&command)
+}
+
+/// Reads the raw response of every getter listed by `help`.
+///
+/// Only [`READ_ONLY_COMMANDS`] are sent, and not when `help` lists them with arguments.
+/// Commands that fail are recorded with their error so a single bad command doesn't abort the dump.
+#[tracing::instrument(skip(focus))]
+pub async fn dump_all(focus: &mut Focus) -> Result<BTreeMap<String, String>> {
+    let commands = help_detailed(focus)
+        .await?
+        .into_iter()
+        .filter(|entry| !entry.takes_args())
+        .map(|entry| entry.command)
+        .collect::<Vec<_>>();
+
+    let mut dump = BTreeMap::new();
+
+    for command in commands.iter().filter(|command| is_read_only(command)) {
+        debug!("Dumping: {}", command);
+        let value = match command_response(focus, command).await {
+            Ok(response) => response,
+            Err(e) => {
+                warn!("Failed to read {}: {}", command, e);
+                format!("<error: {}>", e)
+            }
+        };
+        dump.insert(command.to_string(), value);
+    }
+
+    Ok(dump)
+}
+
+const CRC_ERRORS: &str = "hardware.crc_errors";
+
+/// Number of corrupted messages between the halves since the Neuron started, both sides summed.
+///
+/// Returns `None` for devices that aren't split. The firmware has no command to reset
+/// the counter, compare two readings instead, as [`cable_health`] does.
+#[tracing::instrument(skip(focus))]
+pub async fn hardware_crc_errors_get(focus: &mut Focus) -> Result<Option<u32>> {
+    if !supports(focus, CRC_ERRORS).await? {
+        return Ok(None);
+    }
+
+    let response = command_response(focus, CRC_ERRORS).await?;
+    let errors = parse_crc_errors(&response)
+        .with_context(|| format!("Invalid {} response: {}", CRC_ERRORS, response))?;
+
+    Ok(Some(errors))
+}
+
+/// Sums the per-side counters of a response such as `left: 0 right: 0`.
+fn parse_crc_errors(response: &str) -> Result<u32> {
+    let counters = response
+        .split_whitespace()
+        .filter_map(|part| part.parse::<u32>().ok())
+        .collect::<Vec<_>>();
+    if counters.is_empty() {
+        bail!("No CRC error counters");
+    }
+
+    Ok(counters.iter().sum())
+}
+
+/// CRC errors between the halves sampled over a window.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct CableHealth {
+    pub errors: u32,
+    pub window: Duration,
+    pub errors_per_second: f64,
+}
+
+impl CableHealth {
+    /// A healthy cable produces no CRC errors at all.
+    pub fn is_healthy(&self) -> bool {
+        self.errors == 0
+    }
+}
+
+/// Samples the CRC error counter twice, `window` apart, to judge the cable between the halves.
+///
+/// Returns `None` for devices that aren't split.
+#[tracing::instrument(skip(focus))]
+pub async fn cable_health(focus: &mut Focus, window: Duration) -> Result<Option<CableHealth>> {
+    let Some(before) = hardware_crc_errors_get(focus).await? else {
+        return Ok(None);
+    };
+
+    tokio::time::sleep(window).await;
+
+    let after = hardware_crc_errors_get(focus)
+        .await?
+        .context("CRC error counter disappeared")?;
+    let errors = after.saturating_sub(before);
+    if errors > 0 {
+        warn!(
+            "{} CRC errors in {:?}, check the cable between the halves",
+            errors, window
+        );
+    }
+
+    Ok(Some(CableHealth {
+        errors,
+        window,
+        errors_per_second: errors as f64 / window.as_secs_f64().max(f64::EPSILON),
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn only_getters_are_dumped() {
+        assert!(is_read_only("led.brightness"));
+        assert!(is_read_only("wireless.rf.power"));
+        assert!(!is_read_only("wireless.bluetooth.remove"));
+        assert!(!is_read_only("wireless.energy.disable"));
+        assert!(!is_read_only("eeprom.erase"));
+        assert!(!is_read_only("upgrade.neuron"));
+    }
+
+    #[test]
+    fn parses_per_side_crc_errors() {
+        assert_eq!(parse_crc_errors("left: 0 right: 0").unwrap(), 0);
+        assert_eq!(parse_crc_errors("left: 3 right: 4").unwrap(), 7);
+        assert!(parse_crc_errors("left: right:").is_err());
+    }
+}
@@ -0,0 +1,76 @@
+use anyhow::{Context, Result};
+use dygma_focus::Focus;
+use std::fmt::Display;
+
+/// The data doesn't fit in the free EEPROM space.
+#[derive(Debug)]
+pub struct EepromFull {
+    pub free: u32,
+    pub needed: u32,
+}
+
+impl Display for EepromFull {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "EEPROM write needs {} bytes, only {} are free",
+            self.needed, self.free
+        )
+    }
+}
+
+impl std::error::Error for EepromFull {}
+
+/// Free EEPROM space in bytes, parsed from `eeprom.free`.
+#[tracing::instrument(skip(focus))]
+pub async fn eeprom_free_bytes(focus: &mut Focus) -> Result<u32> {
+    let response = focus.eeprom_free().await?;
+    let free = response
+        .split_whitespace()
+        .next()
+        .context("Empty eeprom.free response")?;
+
+    free.parse()
+        .with_context(|| format!("Invalid eeprom.free response: {}", response))
+}
+
+/// Writes `eeprom.contents`, refusing data larger than the free space with [`EepromFull`].
+///
+/// `data` is the space separated byte list as returned by `eeprom.contents`.
+#[tracing::instrument(skip(focus, data))]
+pub async fn eeprom_contents_set_checked(focus: &mut Focus, data: &str) -> Result<()> {
+    let free = eeprom_free_bytes(focus).await?;
+    check_fits(data, free)?;
+
+    focus.eeprom_contents_set(data).await?;
+
+    Ok(())
+}
+
+fn check_fits(data: &str, free: u32) -> Result<(), EepromFull> {
+    let needed = data.split_whitespace().count() as u32;
+    if needed > free {
+        return Err(EepromFull { free, needed });
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn data_under_or_equal_to_free_space_fits() {
+        assert!(check_fits("1 2", 3).is_ok());
+        assert!(check_fits("1 2 3", 3).is_ok());
+    }
+
+    #[test]
+    fn data_over_free_space_is_refused() {
+        let error = check_fits("1 2 3 4", 3).unwrap_err();
+
+        assert_eq!(error.needed, 4);
+        assert_eq!(error.free, 3);
+    }
+}
@@ -0,0 +1,88 @@
+use crate::color::is_rgbw;
+use crate::command::command_response;
+use anyhow::Result;
+use dygma_focus::hardware::Hardware;
+use dygma_focus::Focus;
+use std::collections::HashSet;
+
+/// Commands the connected firmware lists in `help`.
+pub async fn supported_commands(focus: &mut Focus) -> Result<HashSet<String>> {
+    Ok(focus.help_get().await?.into_iter().collect())
+}
+
+/// Whether the firmware lists the command in `help`.
+pub async fn supports(focus: &mut Focus, command: &str) -> Result<bool> {
+    Ok(focus.help_get().await?.iter().any(|c| c == command))
+}
+
+/// Features of the connected device, for gating UI without matching commands by hand.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Capabilities {
+    pub has_wireless: bool,
+    pub has_bluetooth: bool,
+    pub has_rgbw: bool,
+    pub has_underglow: bool,
+    pub has_superkeys: bool,
+    pub has_macros: bool,
+    pub has_led_fade: bool,
+    pub has_true_sleep: bool,
+}
+
+/// Derives the capabilities from `help`, using the hardware table for what `help` can't tell.
+#[tracing::instrument(skip(focus, hardware))]
+pub async fn capabilities(focus: &mut Focus, hardware: &Hardware) -> Result<Capabilities> {
+    let commands = supported_commands(focus).await?;
+    // The hardware table has no RGBW hint for some devices, the palette response settles it.
+    let has_rgbw = match hardware.rgbw_mode {
+        Some(rgbw) => rgbw,
+        None => is_rgbw(focus, hardware).await?,
+    };
+    let has = |command: &str| commands.contains(command);
+    let has_prefix = |prefix: &str| commands.iter().any(|command| command.starts_with(prefix));
+
+    Ok(Capabilities {
+        has_wireless: has_prefix("wireless."),
+        has_bluetooth: has_prefix("wireless.bluetooth."),
+        has_rgbw,
+        has_underglow: hardware.keyboard_underglow.is_some() || has("led.brightnessUG"),
+        has_superkeys: has("superkeys.map"),
+        has_macros: has("macros.map"),
+        has_led_fade: has("led.fade"),
+        has_true_sleep: has("idleleds.true_sleep"),
+    })
+}
+
+/// A line of the `help` output.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HelpEntry {
+    pub command: String,
+    /// Argument names listed after the command, empty when the firmware lists none.
+    pub args: Vec<String>,
+    pub raw: String,
+}
+
+impl HelpEntry {
+    /// Splits a line into the command and any arguments following it.
+    pub fn parse(line: &str) -> Option<Self> {
+        let mut parts = line.split_whitespace();
+        let command = parts.next()?.to_string();
+
+        Some(Self {
+            command,
+            args: parts.map(str::to_string).collect(),
+            raw: line.to_string(),
+        })
+    }
+
+    pub fn takes_args(&self) -> bool {
+        !self.args.is_empty()
+    }
+}
+
+/// Reads `help` keeping the argument signatures some firmware lists after each command.
+#[tracing::instrument(skip(focus))]
+pub async fn help_detailed(focus: &mut Focus) -> Result<Vec<HelpEntry>> {
+    let response = command_response(focus, "help").await?;
+
+    Ok(response.lines().filter_map(HelpEntry::parse).collect())
+}
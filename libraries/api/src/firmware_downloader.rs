@@ -1,9 +1,11 @@
+use crate::flash::devices::defy::nrf52833_flasher::{DecodedHex, Flasher};
 use anyhow::{bail, Context, Result};
 use dygma_focus::hardware::{DeviceType, Hardware, Product};
-use log::{debug, error, trace};
+use log::{debug, error, trace, warn};
 use regex::Regex;
 use semver::{Version, VersionReq};
 use serde::Deserialize;
+use std::cmp::Reverse;
 use std::fmt::Display;
 use tokio::join;
 
@@ -27,6 +29,107 @@ impl Display for FirmwareRelease {
     }
 }
 
+impl FirmwareRelease {
+    pub fn channel(&self) -> Channel {
+        if self.beta {
+            Channel::Beta
+        } else {
+            Channel::Stable
+        }
+    }
+
+    pub fn firmware_version(&self) -> Result<FirmwareVersion> {
+        FirmwareVersion::parse(&self.version)
+    }
+
+    /// Whether flashing this release would move the device to an older version.
+    ///
+    /// Unparseable versions are never considered a downgrade.
+    pub fn is_downgrade_from(&self, current: &str) -> bool {
+        match (self.firmware_version(), FirmwareVersion::parse(current)) {
+            (Ok(release), Ok(current)) => release < current,
+            _ => false,
+        }
+    }
+}
+
+/// Release notes of a single firmware version.
+#[derive(Debug, Clone)]
+pub struct Changelog {
+    pub version: FirmwareVersion,
+    pub beta: bool,
+    pub notes: String,
+}
+
+/// Release notes of every version after `from` up to and including `to`, oldest first.
+///
+/// Only releases for the same product as the first match are expected in `releases`,
+/// pass the list from [`github_read`]. Releases with unparsable versions are skipped.
+pub fn changelog_between(
+    from: &str,
+    to: &str,
+    releases: &[FirmwareRelease],
+) -> Result<Vec<Changelog>> {
+    let from = FirmwareVersion::parse(from)?;
+    let to = FirmwareVersion::parse(to)?;
+
+    let mut changelogs = releases
+        .iter()
+        .filter_map(|release| match release.firmware_version() {
+            Ok(version) => Some((release, version)),
+            Err(e) => {
+                warn!("Skipping release {}: {}", release, e);
+                None
+            }
+        })
+        .filter(|(_, version)| *version > from && *version <= to)
+        .map(|(release, version)| Changelog {
+            version,
+            beta: release.beta,
+            notes: release.body.trim().to_string(),
+        })
+        .collect::<Vec<_>>();
+
+    changelogs.sort_by(|a, b| a.version.cmp(&b.version));
+
+    Ok(changelogs)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Channel {
+    Stable,
+    Beta,
+}
+
+impl Channel {
+    /// Determines the channel from a version string, such as `v1.0.0-beta.2`.
+    ///
+    /// Any prerelease, not only `-beta`, is treated as a beta. Versions that can't be parsed are
+    /// assumed stable.
+    pub fn from_version(version: &str) -> Self {
+        FirmwareVersion::parse(version)
+            .map(|version| version.channel)
+            .unwrap_or(Channel::Stable)
+    }
+
+    fn from_prerelease(prerelease: &semver::Prerelease) -> Self {
+        if prerelease.is_empty() {
+            Channel::Stable
+        } else {
+            Channel::Beta
+        }
+    }
+}
+
+impl Display for Channel {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Channel::Stable => write!(f, "stable"),
+            Channel::Beta => write!(f, "beta"),
+        }
+    }
+}
+
 #[derive(Debug, Clone)]
 pub struct FirmwareAsset {
     pub name: String,
@@ -39,6 +142,19 @@ pub struct Firmware {
     pub sides: Option<FirmwareNode>,
 }
 
+impl Firmware {
+    /// Parses the main firmware once, so it can be flashed to several devices.
+    pub fn parse(&self) -> Result<Vec<DecodedHex>> {
+        let hex_raw = self
+            .firmware
+            .hex_raw
+            .as_ref()
+            .with_context(|| format!("{} is not an Intel HEX file", self.firmware.name))?;
+
+        Flasher::parse(hex_raw)
+    }
+}
+
 #[derive(Debug, Clone)]
 pub struct FirmwareNode {
     pub name: String,
@@ -55,6 +171,9 @@ pub struct Ctx {
 
 #[derive(Debug, Clone)]
 pub struct Collected {
+    /// Installed firmware version as reported by the `version` command.
+    ///
+    /// `hardware.firmware` formats the version differently, don't use it here.
     pub version: String,
 }
 
@@ -81,14 +200,72 @@ pub struct GitHubInfo {
     pub is_beta: bool,
 }
 
-fn parse_version(version_str: &str) -> Version {
-    let mut clean_version_str = version_str.trim_start_matches('v');
+/// Firmware version as used in release names, such as `v1.2.3-beta.1`.
+///
+/// Ordering follows semver, so prereleases sort before their release.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub struct FirmwareVersion {
+    pub semver: Version,
+    pub prerelease: Option<String>,
+    pub channel: Channel,
+}
+
+impl FirmwareVersion {
+    pub fn parse(version_str: &str) -> Result<Self> {
+        let clean_version_str = version_str.trim().trim_start_matches('v');
+
+        let semver = match Version::parse(clean_version_str) {
+            Ok(version) => version,
+            Err(_) => {
+                // Tolerate suffixes that aren't valid semver prereleases, such as `-beta_2`
+                let (core, suffix) = clean_version_str
+                    .split_once('-')
+                    .with_context(|| format!("Invalid firmware version: {}", version_str))?;
+                let mut version = Version::parse(core)
+                    .with_context(|| format!("Invalid firmware version: {}", version_str))?;
+                version.pre = semver::Prerelease::new(&suffix.replace(['_', ' '], "."))
+                    .with_context(|| format!("Invalid firmware version: {}", version_str))?;
+                version
+            }
+        };
+
+        let prerelease = (!semver.pre.is_empty()).then(|| semver.pre.to_string());
+
+        Ok(Self {
+            channel: Channel::from_prerelease(&semver.pre),
+            semver,
+            prerelease,
+        })
+    }
+
+    /// The version without its prerelease, for matching against version requirements.
+    pub fn core(&self) -> Version {
+        Version::new(self.semver.major, self.semver.minor, self.semver.patch)
+    }
+}
+
+impl std::str::FromStr for FirmwareVersion {
+    type Err = anyhow::Error;
 
-    if let Some(hyphen_index) = clean_version_str.find('-') {
-        clean_version_str = &clean_version_str[..hyphen_index];
+    fn from_str(s: &str) -> Result<Self> {
+        Self::parse(s)
     }
+}
 
-    Version::parse(clean_version_str).unwrap()
+impl Display for FirmwareVersion {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "v{}", self.semver)
+    }
+}
+
+/// Sorts releases newest first.
+///
+/// Releases with an unparsable version keep their relative order after all parsable ones.
+fn sort_releases(releases: &mut [FirmwareRelease]) {
+    releases.sort_by_cached_key(|release| match release.firmware_version() {
+        Ok(version) => (false, Some(Reverse(version))),
+        Err(_) => (true, None),
+    });
 }
 
 pub async fn github_read(context: Ctx) -> Result<GitHubInfo> {
@@ -101,10 +278,16 @@ pub async fn github_read(context: Ctx) -> Result<GitHubInfo> {
         .into_iter()
         .filter(|release| {
             release.name == context.device.info.product.to_string() && {
-                if context.device.info.product == Product::Defy {
-                    return fw_major_version_req.matches(&parse_version(&release.version));
+                match release.firmware_version() {
+                    Ok(version) => {
+                        context.device.info.product != Product::Defy
+                            || fw_major_version_req.matches(&version.core())
+                    }
+                    Err(e) => {
+                        warn!("Skipping release {}: {}", release, e);
+                        false
+                    }
                 }
-                true
             }
         })
         .collect::<Vec<_>>();
@@ -115,7 +298,7 @@ pub async fn github_read(context: Ctx) -> Result<GitHubInfo> {
         bail!("{}", msg);
     }
 
-    final_releases.sort_by(|a, b| b.version.cmp(&a.version));
+    sort_releases(&mut final_releases);
 
     if context.device.bootloader {
         return Ok(GitHubInfo {
@@ -126,7 +309,19 @@ pub async fn github_read(context: Ctx) -> Result<GitHubInfo> {
     }
 
     let latest = &final_releases[0];
-    let is_updated = context.collected.version == latest.version;
+    let is_updated = match (
+        FirmwareVersion::parse(&context.collected.version),
+        latest.firmware_version(),
+    ) {
+        (Ok(current), Ok(latest)) => current.semver == latest.semver,
+        _ => {
+            warn!(
+                "Comparing unparsable versions as text: {} and {}",
+                context.collected.version, latest.version
+            );
+            context.collected.version.trim() == latest.version.trim()
+        }
+    };
     let is_beta = latest.beta;
 
     Ok(GitHubInfo {
@@ -158,7 +353,7 @@ pub async fn load_available_firmware_versions(allow_beta: bool) -> Result<Vec<Fi
             }
             let name = release_data[0].to_string();
             let version = release_data[1].to_string();
-            let is_beta = release.beta || version.contains("-beta");
+            let is_beta = release.beta || Channel::from_version(&version) == Channel::Beta;
             if !allow_beta && is_beta {
                 return None;
             }
@@ -184,58 +379,63 @@ pub async fn load_available_firmware_versions(allow_beta: bool) -> Result<Vec<Fi
     Ok(releases)
 }
 
-pub async fn download_firmware(
-    hardware: &Hardware,
-    firmware_release: &FirmwareRelease,
-) -> Result<Firmware> {
+const SIDES_FILE_NAME: &str = "keyscanner.bin";
+
+/// Whether the device has keyboard halves with their own keyscanner firmware.
+pub fn has_side_firmware(hardware: &Hardware) -> bool {
+    hardware.info.product == Product::Defy
+}
+
+/// Name of the release asset holding the main (Neuron) firmware for the hardware.
+pub fn firmware_file_name(hardware: &Hardware) -> Result<&'static str> {
     match hardware.info.product {
-        Product::Raise => download_firmware_raise(firmware_release).await,
+        Product::Raise => Ok("firmware.hex"),
         Product::Defy => match hardware.info.device_type {
-            DeviceType::Wireless => {
-                download_firmware_defy(firmware_release, "Wireless_neuron.hex").await
-            }
-            DeviceType::Wired => download_firmware_defy(firmware_release, "Wired_neuron.uf2").await,
+            DeviceType::Wireless => Ok("Wireless_neuron.hex"),
+            DeviceType::Wired => Ok("Wired_neuron.uf2"),
             _ => bail!("Invalid device type"),
         },
     }
 }
 
-async fn download_firmware_raise(firmware_release: &FirmwareRelease) -> Result<Firmware> {
-    let firmware_file = "firmware.hex";
-    let matched = firmware_release
-        .assets
-        .iter()
-        .find(|asset| asset.name == firmware_file)
-        .context("Firmware not found")?;
-
-    let fw = obtain_firmware_file(firmware_file, &matched.url).await?;
+/// Release assets needed to flash the hardware, main firmware first.
+pub fn expected_assets(hardware: &Hardware) -> Result<Vec<&'static str>> {
+    let mut assets = vec![firmware_file_name(hardware)?];
+    if has_side_firmware(hardware) {
+        assets.push(SIDES_FILE_NAME);
+    }
 
-    Ok(Firmware {
-        firmware: fw,
-        sides: None,
-    })
+    Ok(assets)
 }
 
-async fn download_firmware_defy(
+/// Downloads the main firmware, plus the side firmware when the hardware has detachable halves.
+pub async fn download_firmware(
+    hardware: &Hardware,
     firmware_release: &FirmwareRelease,
-    firmware_file_name: &str,
 ) -> Result<Firmware> {
-    let matched_fw = firmware_release
-        .assets
-        .iter()
-        .find(|asset| asset.name == firmware_file_name)
-        .context("Firmware not found")?;
+    let find_asset = |file_name: &str| {
+        firmware_release
+            .assets
+            .iter()
+            .find(|asset| asset.name == file_name)
+            .with_context(|| format!("Firmware asset {} not found", file_name))
+    };
+
+    let firmware_file_name = firmware_file_name(hardware)?;
+    let matched_fw = find_asset(firmware_file_name)?;
+
+    if !has_side_firmware(hardware) {
+        return Ok(Firmware {
+            firmware: obtain_firmware_file(firmware_file_name, &matched_fw.url).await?,
+            sides: None,
+        });
+    }
 
-    let firmware_sides_file_name = "keyscanner.bin";
-    let matched_sides = firmware_release
-        .assets
-        .iter()
-        .find(|asset| asset.name == firmware_sides_file_name)
-        .context("Firmware sides not found")?;
+    let matched_sides = find_asset(SIDES_FILE_NAME)?;
 
     let (firmware, sides) = join!(
         obtain_firmware_file(firmware_file_name, &matched_fw.url),
-        obtain_firmware_file(firmware_sides_file_name, &matched_sides.url)
+        obtain_firmware_file(SIDES_FILE_NAME, &matched_sides.url)
     );
 
     Ok(Firmware {
@@ -280,3 +480,90 @@ pub async fn obtain_firmware_file(firmware_file_name: &str, url: &str) -> Result
         Ok(firmware_node)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use dygma_focus::hardware::types::hardware_physical::{
+        DEFY_WIRED, DEFY_WIRELESS, RAISE_ANSI, RAISE_ISO,
+    };
+
+    fn release(version: &str) -> FirmwareRelease {
+        FirmwareRelease {
+            name: "Defy".to_string(),
+            version: version.to_string(),
+            body: String::new(),
+            assets: Vec::new(),
+            beta: version.contains("-beta"),
+        }
+    }
+
+    #[test]
+    fn parses_beta_version() {
+        let version = FirmwareVersion::parse("v1.2.3-beta.1").unwrap();
+
+        assert_eq!(version.semver, Version::parse("1.2.3-beta.1").unwrap());
+        assert_eq!(version.prerelease.as_deref(), Some("beta.1"));
+        assert_eq!(version.channel, Channel::Beta);
+        assert_eq!(version.core(), Version::new(1, 2, 3));
+    }
+
+    #[test]
+    fn parses_stable_version_without_prefix() {
+        let version = FirmwareVersion::parse("1.10.0").unwrap();
+
+        assert_eq!(version.semver, Version::new(1, 10, 0));
+        assert_eq!(version.prerelease, None);
+        assert_eq!(version.channel, Channel::Stable);
+        assert!(version > FirmwareVersion::parse("v1.9.0").unwrap());
+    }
+
+    #[test]
+    fn any_prerelease_is_beta() {
+        assert_eq!(Channel::from_version("v1.2.3-beta.1"), Channel::Beta);
+        assert_eq!(Channel::from_version("v1.2.3-rc.1"), Channel::Beta);
+        assert_eq!(Channel::from_version("1.2.3-alpha"), Channel::Beta);
+        assert_eq!(Channel::from_version("v1.2.3-beta_2"), Channel::Beta);
+        assert_eq!(Channel::from_version("v1.2.3"), Channel::Stable);
+        assert_eq!(Channel::from_version("not-a-version"), Channel::Stable);
+    }
+
+    #[test]
+    fn rejects_malformed_version() {
+        assert!(FirmwareVersion::parse("not-a-version").is_err());
+        assert!(FirmwareVersion::parse("").is_err());
+    }
+
+    #[test]
+    fn sorts_parsable_releases_first_newest_first() {
+        let mut releases = ["1.2.3", "garbage", "v1.10.0", "v1.2.3-beta.1", "also bad"]
+            .into_iter()
+            .map(release)
+            .collect::<Vec<_>>();
+
+        sort_releases(&mut releases);
+
+        let versions = releases
+            .iter()
+            .map(|release| release.version.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            versions,
+            ["v1.10.0", "1.2.3", "v1.2.3-beta.1", "garbage", "also bad"]
+        );
+    }
+
+    #[test]
+    fn expected_assets_per_product() {
+        assert_eq!(
+            expected_assets(&DEFY_WIRED).unwrap(),
+            ["Wired_neuron.uf2", SIDES_FILE_NAME]
+        );
+        assert_eq!(
+            expected_assets(&DEFY_WIRELESS).unwrap(),
+            ["Wireless_neuron.hex", SIDES_FILE_NAME]
+        );
+        assert_eq!(expected_assets(&RAISE_ANSI).unwrap(), ["firmware.hex"]);
+        assert_eq!(expected_assets(&RAISE_ISO).unwrap(), ["firmware.hex"]);
+    }
+}
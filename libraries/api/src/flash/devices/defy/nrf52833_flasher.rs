@@ -1,15 +1,107 @@
-use anyhow::{bail, Result};
+use crate::devices::FlashingGuard;
+use anyhow::{anyhow, bail, Context, Result};
 use dygma_focus::hardware::{Device, Product};
 use dygma_focus::Focus;
-use log::info;
+use log::{info, warn};
 use rayon::prelude::*;
-use std::usize;
+use std::time::{Duration, Instant};
 use tracing::trace;
 
-const PACKET_SIZE: usize = 4096;
+/// Bytes of firmware sent per erase/write cycle.
+pub const DEFAULT_PACKET_SIZE: usize = 4096;
+/// Bytes handed to the serial port per write.
+pub const DEFAULT_WRITE_CHUNK_SIZE: usize = 200;
+
+/// The nRF52833 has 512 KiB of flash starting at address 0.
+const FLASH_END: u32 = 0x0008_0000;
+
+const ACK_TIMEOUT: Duration = Duration::from_secs(5);
+/// Erasing the application area takes considerably longer than a write.
+const ERASE_ACK_TIMEOUT: Duration = Duration::from_secs(30);
+const MAX_WRITE_ATTEMPTS: usize = 3;
+/// Weight of the latest packet in the throughput moving average.
+const THROUGHPUT_SMOOTHING: f64 = 0.3;
+
+/// Reply from the bootloader after a command.
+///
+/// The bootloader frames its replies like Focus responses, terminated by `\r\n.\r\n`
+/// (`0x0D 0x0A 0x2E 0x0D 0x0A`). A command that succeeded is acknowledged with that
+/// terminator and no payload, the same ack `Focus::read_string` logs as `[Ack]`; NUL
+/// (`0x00`) padding and whitespace around it are ignored. The bootloader has no dedicated
+/// NAK byte, so any other payload, including data garbled on the wire, is treated as a
+/// failure and the command is retried or the flash aborted.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum AckKind {
+    Ack,
+    Unexpected(String),
+}
+
+impl AckKind {
+    /// Classifies a reply with its terminator already stripped, as `Focus::read_string` returns it.
+    pub fn parse(response: &str) -> Self {
+        match response.trim() {
+            "" => AckKind::Ack,
+            other => AckKind::Unexpected(other.to_string()),
+        }
+    }
+}
+
+/// Progress of the write phase, reported after every packet.
+#[derive(Debug, Clone, Copy)]
+pub struct FlashProgress {
+    pub written: usize,
+    pub total: usize,
+    /// Moving average of the recent transfer rate, in bytes per second.
+    pub bytes_per_second: f64,
+    /// Estimated time until every byte is written, `None` until a rate is known.
+    pub eta: Option<Duration>,
+}
+
+impl FlashProgress {
+    pub fn remaining(&self) -> usize {
+        self.total - self.written
+    }
+}
+
+/// Smoothed throughput across packets, so the ETA doesn't jump with every packet.
+struct ThroughputTracker {
+    last: Instant,
+    bytes_per_second: f64,
+}
+
+impl ThroughputTracker {
+    fn new() -> Self {
+        Self {
+            last: Instant::now(),
+            bytes_per_second: 0.0,
+        }
+    }
+
+    fn record(&mut self, bytes: usize) -> f64 {
+        let now = Instant::now();
+        let elapsed = now.duration_since(self.last).as_secs_f64();
+        self.last = now;
+
+        if elapsed > 0.0 {
+            let rate = bytes as f64 / elapsed;
+            self.bytes_per_second = if self.bytes_per_second == 0.0 {
+                rate
+            } else {
+                THROUGHPUT_SMOOTHING * rate + (1.0 - THROUGHPUT_SMOOTHING) * self.bytes_per_second
+            };
+        }
+
+        self.bytes_per_second
+    }
+}
 
 pub struct Flasher {
     focus: Focus,
+    packet_size: usize,
+    write_chunk_size: usize,
+    erase_address: Option<u32>,
+    progress: Option<Box<dyn Fn(FlashProgress) + Send + Sync>>,
+    _flashing: FlashingGuard,
 }
 
 impl Flasher {
@@ -19,18 +111,68 @@ impl Flasher {
         } else if device.hardware.bootloader {
             bail!("Device is in bootloader mode");
         }
+        let flashing = FlashingGuard::acquire(device)?;
+
         Ok(Self {
             focus: Focus::new_via_device(device)?,
+            packet_size: DEFAULT_PACKET_SIZE,
+            write_chunk_size: DEFAULT_WRITE_CHUNK_SIZE,
+            erase_address: None,
+            progress: None,
+            _flashing: flashing,
         })
     }
 
+    /// Sets how many firmware bytes are sent between acknowledgements.
+    ///
+    /// Larger packets flash faster, smaller packets recover better from unreliable links.
+    pub fn with_packet_size(mut self, packet_size: usize) -> Result<Self> {
+        if packet_size == 0 {
+            bail!("Packet size must be greater than zero");
+        }
+        self.packet_size = packet_size;
+
+        Ok(self)
+    }
+
+    /// Sets how many bytes are written to the serial port at a time.
+    ///
+    /// Some USB-serial adapters drop bytes with large writes, smaller chunks are slower but safer.
+    pub fn with_write_chunk_size(mut self, write_chunk_size: usize) -> Result<Self> {
+        if write_chunk_size == 0 {
+            bail!("Write chunk size must be greater than zero");
+        }
+        self.write_chunk_size = write_chunk_size;
+
+        Ok(self)
+    }
+
+    /// Called after every packet is written, with the throughput and estimated time remaining.
+    pub fn with_progress(
+        mut self,
+        progress: impl Fn(FlashProgress) + Send + Sync + 'static,
+    ) -> Self {
+        self.progress = Some(Box::new(progress));
+
+        self
+    }
+
     // TODO: Refactor to reduce allocations
     #[tracing::instrument(skip(self, file_content))]
     pub async fn flash(&mut self, file_content: &str) -> Result<()> {
+        let decoded_hexes = Self::parse(file_content)?;
+
+        self.flash_parsed(&decoded_hexes).await
+    }
+
+    /// Decodes an Intel HEX file into data records with absolute addresses.
+    ///
+    /// The result can be flashed to any number of devices with [`Flasher::flash_parsed`].
+    #[tracing::instrument(skip(file_content))]
+    pub fn parse(file_content: &str) -> Result<Vec<DecodedHex>> {
         let decoded = Self::ihex_decode_lines(file_content)?;
 
         let mut decoded_hexes = Vec::new();
-        let mut total = 0;
         let mut segment = 0;
         let mut linear = 0;
 
@@ -39,25 +181,35 @@ impl Flasher {
             match hex.record_type {
                 RecordType::Unknown(_) => {}
                 RecordType::ESA => {
-                    segment = u32::from_str_radix(&hex.str[8..8 + hex_length], 16)? * 16;
+                    segment = u32::from_str_radix(&hex.str[8..8 + hex_length], 16)?
+                        .checked_mul(16)
+                        .context("Segment address overflow")?;
                     linear = 0;
 
                     continue;
                 }
                 RecordType::ELA => {
-                    linear = u32::from_str_radix(&hex.str[8..8 + hex_length], 16)? * 65536;
+                    linear = u32::from_str_radix(&hex.str[8..8 + hex_length], 16)?
+                        .checked_mul(65536)
+                        .context("Linear address overflow")?;
                     segment = 0;
 
                     continue;
                 }
                 RecordType::DAT => {
-                    total += hex.len as usize;
-
-                    if segment > 0 {
-                        hex.address += segment;
-                    }
-                    if linear > 0 {
-                        hex.address += linear;
+                    hex.address = hex
+                        .address
+                        .checked_add(segment)
+                        .and_then(|address| address.checked_add(linear))
+                        .context("Record address overflow")?;
+
+                    let end = hex.address as u64 + hex.len as u64;
+                    if end > FLASH_END as u64 {
+                        bail!(
+                            "Record at {} ends outside flash (end {})",
+                            num_to_hex(hex.address),
+                            num_to_hex(FLASH_END)
+                        );
                     }
 
                     decoded_hexes.push(hex);
@@ -65,17 +217,31 @@ impl Flasher {
             }
         }
 
+        Ok(decoded_hexes)
+    }
+
+    /// Flashes data records produced by [`Flasher::parse`].
+    #[tracing::instrument(skip(self, decoded_hexes))]
+    pub async fn flash_parsed(&mut self, decoded_hexes: &[DecodedHex]) -> Result<()> {
+        let Some(first) = decoded_hexes.first() else {
+            bail!("Firmware contains no data records");
+        };
+
+        let total_bytes = decoded_hexes
+            .iter()
+            .map(|hex| hex.len as usize)
+            .sum::<usize>();
+        let mut total = total_bytes;
         let mut hex_count = 0;
-        let mut address = decoded_hexes[0].address;
+        let mut address = first.address;
 
-        // ERASE device
-        let s = format!("E{}#", num_to_hex(address));
-        trace!("{}", &s);
-        self.write(s.as_bytes()).await?;
-        self.focus.read_string().await?;
+        info!("Erasing");
+        self.erase(address).await?;
+        info!("Writing");
+        let mut throughput = ThroughputTracker::new();
 
         while total > 0 {
-            let buffer_size = std::cmp::min(total, PACKET_SIZE);
+            let buffer_size = std::cmp::min(total, self.packet_size);
 
             let mut accumulated_length = 0;
             let start_hex_count = hex_count;
@@ -98,20 +264,97 @@ impl Flasher {
                 address += decoded_hex.len as u32;
                 total -= decoded_hex.len as usize;
             }
+
+            if let Some(progress) = &self.progress {
+                let bytes_per_second = throughput.record(accumulated_length);
+                let eta = (bytes_per_second > 0.0)
+                    .then(|| Duration::from_secs_f64(total as f64 / bytes_per_second));
+
+                progress(FlashProgress {
+                    written: total_bytes - total,
+                    total: total_bytes,
+                    bytes_per_second,
+                    eta,
+                });
+            }
         }
 
         trace!("S#");
         self.write("S#".as_bytes()).await?;
 
         trace!("Wait for ACK");
-        self.focus.read_string().await?;
+        if let AckKind::Unexpected(response) = self.read_ack(ACK_TIMEOUT).await? {
+            bail!("Bootloader rejected the start command: {}", response);
+        }
 
         info!("Finished flashing");
 
         Ok(())
     }
 
+    async fn erase(&mut self, address: u32) -> Result<()> {
+        self.erase_address = Some(address);
+
+        let s = format!("E{}#", num_to_hex(address));
+        trace!("{}", &s);
+        self.write(s.as_bytes()).await?;
+        if let AckKind::Unexpected(response) = self.read_ack(ERASE_ACK_TIMEOUT).await? {
+            bail!("Bootloader rejected the erase: {}", response);
+        }
+
+        Ok(())
+    }
+
+    /// Returns the device to a known state after a failed or hung flash.
+    ///
+    /// Discards any late reply and erases the application area again, so the bootloader
+    /// doesn't start a partially written image and is ready for a new flash.
+    /// Safe to call once the flashing future has been dropped or has returned an error.
+    /// Does nothing if flashing never reached the erase step.
+    #[tracing::instrument(skip(self))]
+    pub async fn abort(&mut self) -> Result<()> {
+        let Some(address) = self.erase_address else {
+            info!("Abort: nothing was written");
+            return Ok(());
+        };
+
+        info!("Abort: draining pending reply");
+        if let Ok(ack) = self.read_ack(Duration::from_millis(500)).await {
+            trace!("Discarded: {:?}", ack);
+        }
+
+        info!("Abort: erasing partially written firmware");
+        self.erase(address).await?;
+
+        info!("Abort: device is waiting in the bootloader");
+
+        Ok(())
+    }
+
     async fn local_write(&mut self, address: u32, decoded_hex: &DecodedHex) -> Result<()> {
+        for attempt in 1..=MAX_WRITE_ATTEMPTS {
+            let AckKind::Unexpected(response) = self.try_local_write(address, decoded_hex).await?
+            else {
+                return Ok(());
+            };
+
+            warn!(
+                "Write at {} rejected ({}), attempt {}/{}",
+                num_to_hex(address),
+                response,
+                attempt,
+                MAX_WRITE_ATTEMPTS
+            );
+        }
+
+        bail!(
+            "Write at {} failed after {} attempts",
+            num_to_hex(address),
+            MAX_WRITE_ATTEMPTS
+        );
+    }
+
+    async fn try_local_write(&mut self, address: u32, decoded_hex: &DecodedHex) -> Result<AckKind> {
         let length_as_hex = num_to_hex(decoded_hex.len as u32);
 
         let s = format!("U{}#", &length_as_hex);
@@ -127,14 +370,26 @@ impl Flasher {
         self.write(s.as_bytes()).await?;
 
         trace!("Wait for ACK");
-        self.focus.read_string().await?;
+        self.read_ack(ACK_TIMEOUT).await
+    }
 
-        Ok(())
+    /// Waits for the bootloader to acknowledge the previous command.
+    pub async fn read_ack(&mut self, timeout: Duration) -> Result<AckKind> {
+        let response = tokio::time::timeout(timeout, self.focus.read_string())
+            .await
+            .map_err(|_| anyhow!("Timed out waiting for ACK after {:?}", timeout))??;
+
+        let ack = AckKind::parse(&response);
+        if let AckKind::Unexpected(response) = &ack {
+            trace!("Unexpected reply instead of ACK: {}", response);
+        }
+
+        Ok(ack)
     }
 
     #[tracing::instrument(skip(self, buffer))]
     pub async fn write(&mut self, buffer: &[u8]) -> Result<()> {
-        for chunk in buffer.chunks(200) {
+        for chunk in buffer.chunks(self.write_chunk_size) {
             self.focus.write_bytes(chunk).await?;
         }
 
@@ -199,3 +454,57 @@ pub enum RecordType {
     ESA,
     ELA,
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DATA_RECORD: &str = ":0400000001020304F2";
+
+    #[test]
+    fn empty_reply_is_an_ack() {
+        assert_eq!(AckKind::parse(""), AckKind::Ack);
+        assert_eq!(AckKind::parse("\r\n"), AckKind::Ack);
+    }
+
+    #[test]
+    fn any_payload_is_unexpected() {
+        assert_eq!(
+            AckKind::parse("\u{15}"),
+            AckKind::Unexpected("\u{15}".to_string())
+        );
+        assert_eq!(
+            AckKind::parse(" error \r\n"),
+            AckKind::Unexpected("error".to_string())
+        );
+    }
+
+    #[test]
+    fn parse_applies_linear_address() {
+        let hex = format!(":020000040001F9\n{}\n:00000001FF", DATA_RECORD);
+        let decoded = Flasher::parse(&hex).unwrap();
+
+        assert_eq!(decoded.len(), 1);
+        assert_eq!(decoded[0].address, 0x0001_0000);
+        assert_eq!(decoded[0].data, [1, 2, 3, 4]);
+    }
+
+    #[test]
+    fn parse_rejects_record_outside_flash() {
+        let hex = format!(":020000040008F2\n{}\n:00000001FF", DATA_RECORD);
+        let error = Flasher::parse(&hex).unwrap_err();
+
+        assert!(
+            error.to_string().contains("ends outside flash"),
+            "{}",
+            error
+        );
+    }
+
+    #[test]
+    fn parse_rejects_wrapping_address() {
+        let hex = ":02000004FFFFFC\n:04FFFF0001020304F2\n:00000001FF";
+
+        assert!(Flasher::parse(hex).is_err());
+    }
+}
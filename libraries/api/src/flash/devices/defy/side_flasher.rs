@@ -1,15 +1,62 @@
+use crate::devices::FlashingGuard;
 use crate::firmware_downloader::FirmwareNode;
-use anyhow::Result;
+use anyhow::{bail, Context, Result};
 use crc32fast::Hasher;
 use dygma_focus::hardware::Device;
-use dygma_focus::Focus;
-use log::info;
+use dygma_focus::prelude::*;
+use log::{info, warn};
 use rayon::prelude::*;
+use tracing::trace;
+
+const MAX_CHUNK_ATTEMPTS: usize = 3;
+
+/// Parsed `upgrade.keyscanner.getInfo` response.
+///
+/// The keyscanner answers with whitespace separated fields:
+/// `<hardware id> <flash start> <program valid> <program version> <program CRC32>`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct KeyscannerInfo {
+    pub hardware_id: u32,
+    pub flash_start: u32,
+    pub valid: bool,
+    pub version: u32,
+    pub crc: u32,
+}
+
+impl KeyscannerInfo {
+    pub fn parse(response: &str) -> Result<Self> {
+        let parts = response.split_whitespace().collect::<Vec<_>>();
+        if parts.len() < 5 {
+            bail!("Unexpected keyscanner info: {}", response);
+        }
+
+        let number = |index: usize, name: &str| {
+            parse_number(parts[index])
+                .with_context(|| format!("Invalid {}: {}", name, parts[index]))
+        };
+
+        Ok(Self {
+            hardware_id: number(0, "hardware id")?,
+            flash_start: number(1, "flash start")?,
+            valid: matches!(parts[2], "true" | "1"),
+            version: number(3, "version")?,
+            crc: number(4, "CRC")?,
+        })
+    }
+}
+
+fn parse_number(value: &str) -> Result<u32> {
+    match value.strip_prefix("0x") {
+        Some(hex) => Ok(u32::from_str_radix(hex, 16)?),
+        None => Ok(value.parse()?),
+    }
+}
 
 pub struct SideFlasher {}
 
 impl SideFlasher {
     pub async fn prepare_neuron(device: &Device) -> Result<()> {
+        let _flashing = FlashingGuard::acquire(device)?;
         let mut focus = Focus::new_via_device(device)?;
 
         info!("Upgrading the Neuron...");
@@ -18,12 +65,114 @@ impl SideFlasher {
         Ok(())
     }
 
+    #[tracing::instrument(skip(device, firmware))]
     pub async fn flash_side(device: &Device, firmware: &FirmwareNode) -> Result<()> {
+        let _flashing = FlashingGuard::acquire(device)?;
         let mut focus = Focus::new_via_device(device)?;
+        let chunks = Self::prepare_chunks(firmware)?;
+        let crc = crc32fast::hash(&firmware.bytes);
+
+        for side in [Side::Right, Side::Left] {
+            Self::flash_single_side(&mut focus, side, &chunks, crc).await?;
+        }
+
+        Ok(())
+    }
+
+    pub async fn get_info(focus: &mut Focus) -> Result<KeyscannerInfo> {
+        let response = focus.upgrade_keyscanner_get_info().await?;
+
+        KeyscannerInfo::parse(&response)
+    }
+
+    /// Flashes one side, always sending `upgrade.keyscanner.finish` afterwards.
+    ///
+    /// The keyscanner stays in upgrade mode until finished, so it is sent even when entering
+    /// upgrade mode or writing a chunk fails.
+    async fn flash_single_side(
+        focus: &mut Focus,
+        side: Side,
+        chunks: &[Vec<u8>],
+        crc: u32,
+    ) -> Result<()> {
+        info!("Flashing {:?} side", side);
+
+        let result = Self::write_side(focus, side, chunks, crc).await;
+        let finished = focus.upgrade_keyscanner_finish().await;
+
+        match (result, finished) {
+            (Ok(()), Ok(_)) => {
+                info!("Finished flashing {:?} side", side);
+                Ok(())
+            }
+            (Ok(()), Err(e)) => Err(e.context(format!("Failed to finish {:?} side", side))),
+            (Err(e), Ok(_)) => Err(e),
+            (Err(e), Err(finish_error)) => {
+                warn!("Failed to finish {:?} side: {}", side, finish_error);
+                Err(e)
+            }
+        }
+    }
+
+    async fn write_side(focus: &mut Focus, side: Side, chunks: &[Vec<u8>], crc: u32) -> Result<()> {
+        if !focus.upgrade_keyscanner_begin(side).await? {
+            bail!("{:?} side did not enter upgrade mode", side);
+        }
+
+        let info = Self::get_info(focus).await?;
+        trace!("Keyscanner info: {:?}", info);
+        if info.valid && info.crc == crc {
+            info!("{:?} side already has this firmware, skipping", side);
+            return Ok(());
+        }
+
+        for (index, chunk) in chunks.iter().enumerate() {
+            Self::write_chunk(focus, index, chunk).await?;
+        }
+
+        let written = Self::get_info(focus).await?;
+        trace!("Keyscanner info after writing: {:?}", written);
+        if written.crc != crc {
+            bail!(
+                "{:?} side reports CRC {:#010x} after flashing, expected {:#010x}",
+                side,
+                written.crc,
+                crc
+            );
+        }
 
         Ok(())
     }
 
+    /// Sends a chunk and waits for the keyscanner to confirm the CRC.
+    ///
+    /// The keyscanner computes the CRC32 of the received data and compares it with the CRC at
+    /// the end of the chunk, answering `true` on a match. Rejected chunks are resent.
+    async fn write_chunk(focus: &mut Focus, index: usize, chunk: &[u8]) -> Result<()> {
+        for attempt in 1..=MAX_CHUNK_ATTEMPTS {
+            trace!("Writing chunk {}", index);
+            focus.write_bytes(b"upgrade.keyscanner.sendWrite ").await?;
+            focus.write_bytes(chunk).await?;
+
+            trace!("Wait for CRC ACK");
+            let ack = focus.read_string().await?;
+            if ack.trim() == "true" {
+                return Ok(());
+            }
+
+            warn!(
+                "Chunk {} CRC rejected, attempt {}/{}",
+                index, attempt, MAX_CHUNK_ATTEMPTS
+            );
+        }
+
+        bail!(
+            "Chunk {} failed CRC validation after {} attempts",
+            index,
+            MAX_CHUNK_ATTEMPTS
+        );
+    }
+
     #[tracing::instrument(skip(firmware))]
     pub fn prepare_chunks(firmware: &FirmwareNode) -> Result<Vec<Vec<u8>>> {
         let data_size = 256;
@@ -61,3 +210,30 @@ impl SideFlasher {
         Ok(chunks)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_keyscanner_info() {
+        let info = KeyscannerInfo::parse("1 0x00010000 true 3 0x1A2B3C4D").unwrap();
+
+        assert_eq!(
+            info,
+            KeyscannerInfo {
+                hardware_id: 1,
+                flash_start: 0x10000,
+                valid: true,
+                version: 3,
+                crc: 0x1A2B3C4D,
+            }
+        );
+    }
+
+    #[test]
+    fn rejects_truncated_keyscanner_info() {
+        assert!(KeyscannerInfo::parse("1 0x00010000 true").is_err());
+        assert!(KeyscannerInfo::parse("1 0x00010000 false 3 nope").is_err());
+    }
+}
@@ -1,12 +1,112 @@
 pub mod devices;
 
+use crate::firmware_downloader::{
+    firmware_file_name, Channel, Firmware, FirmwareRelease, FirmwareVersion,
+};
 use anyhow::Result;
 use chrono::format::StrftimeItems;
 use chrono::Local;
-use log::info;
+use dygma_focus::hardware::Hardware;
+use log::{info, warn};
 use serde::Serialize;
+use std::fmt::Display;
 use tokio::fs;
 
+#[derive(Debug)]
+pub enum FlashError {
+    /// The firmware was built for different hardware than the connected device.
+    FirmwareDeviceMismatch { firmware: String, device: String },
+}
+
+impl Display for FlashError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            FlashError::FirmwareDeviceMismatch { firmware, device } => {
+                write!(f, "Firmware {} does not target {}", firmware, device)
+            }
+        }
+    }
+}
+
+impl std::error::Error for FlashError {}
+
+/// Refuses firmware whose release or asset doesn't match the connected hardware.
+pub fn check_firmware_target(
+    hardware: &Hardware,
+    release: &FirmwareRelease,
+    firmware: &Firmware,
+) -> Result<()> {
+    let expected_file_name = firmware_file_name(hardware)?;
+
+    if release.name != hardware.info.product.to_string()
+        || firmware.firmware.name != expected_file_name
+    {
+        return Err(FlashError::FirmwareDeviceMismatch {
+            firmware: format!("{} ({})", release, firmware.firmware.name),
+            device: hardware.info.display_name.to_string(),
+        }
+        .into());
+    }
+
+    Ok(())
+}
+
+/// Conditions that should be confirmed by the user before flashing.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum FlashWarning {
+    StableToBeta { current: String, target: String },
+    MajorDowngrade { current: String, target: String },
+}
+
+impl Display for FlashWarning {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            FlashWarning::StableToBeta { current, target } => write!(
+                f,
+                "Switching from stable firmware {} to beta firmware {}",
+                current, target
+            ),
+            FlashWarning::MajorDowngrade { current, target } => write!(
+                f,
+                "Downgrading firmware major version from {} to {}",
+                current, target
+            ),
+        }
+    }
+}
+
+/// Compares the installed version with the release about to be flashed.
+pub fn flash_warnings(current_version: &str, release: &FirmwareRelease) -> Vec<FlashWarning> {
+    let mut warnings = Vec::new();
+
+    if Channel::from_version(current_version) == Channel::Stable
+        && release.channel() == Channel::Beta
+    {
+        warnings.push(FlashWarning::StableToBeta {
+            current: current_version.to_string(),
+            target: release.version.clone(),
+        });
+    }
+
+    if let (Ok(target), Ok(current)) = (
+        release.firmware_version(),
+        FirmwareVersion::parse(current_version),
+    ) {
+        if target.semver.major < current.semver.major {
+            warnings.push(FlashWarning::MajorDowngrade {
+                current: current_version.to_string(),
+                target: release.version.clone(),
+            });
+        }
+    }
+
+    for warning in &warnings {
+        warn!("{}", warning);
+    }
+
+    warnings
+}
+
 /// Formats date for create name of backup file.
 ///
 /// Example output: "2019-07-12-19_40_56"
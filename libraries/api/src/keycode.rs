@@ -0,0 +1,232 @@
+use std::fmt::Display;
+use std::ops::RangeInclusive;
+
+/// Raw keycode as stored in the keymap.
+///
+/// Ranges follow the Dygma Kaleidoscope fork, as used by Bazecor.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Keycode(pub u16);
+
+pub const NO_KEY: u16 = 0;
+pub const TRANSPARENT: u16 = 65535;
+
+/// HID keyboard usages, `A` through `Right GUI`.
+pub const KEYBOARD: RangeInclusive<u16> = 4..=231;
+/// HID modifiers, `Left Control` through `Right GUI`.
+pub const MODIFIER: RangeInclusive<u16> = 224..=231;
+/// HID keyboard usages combined with held modifiers in the high byte.
+pub const MODIFIED_KEYBOARD: RangeInclusive<u16> = 256..=8191;
+pub const LED_EFFECT: RangeInclusive<u16> = 17152..=17154;
+pub const LAYER_LOCK: RangeInclusive<u16> = 17408..=17417;
+pub const LAYER_SHIFT: RangeInclusive<u16> = 17450..=17459;
+pub const LAYER_MOVE: RangeInclusive<u16> = 17492..=17501;
+pub const CONSUMER: RangeInclusive<u16> = 18432..=24575;
+pub const MOUSE: RangeInclusive<u16> = 20480..=20735;
+pub const ONE_SHOT_MODIFIER: RangeInclusive<u16> = 49153..=49160;
+pub const ONE_SHOT_LAYER: RangeInclusive<u16> = 49161..=49168;
+pub const DUAL_FUNCTION: RangeInclusive<u16> = 49169..=53266;
+pub const TAP_DANCE: RangeInclusive<u16> = 53267..=53282;
+pub const LEADER: RangeInclusive<u16> = 53283..=53290;
+pub const MACRO: RangeInclusive<u16> = 53852..=53915;
+pub const SUPERKEY: RangeInclusive<u16> = 53916..=54107;
+
+const VALID_RANGES: &[RangeInclusive<u16>] = &[
+    KEYBOARD,
+    MODIFIED_KEYBOARD,
+    LED_EFFECT,
+    LAYER_LOCK,
+    LAYER_SHIFT,
+    LAYER_MOVE,
+    CONSUMER,
+    MOUSE,
+    ONE_SHOT_MODIFIER,
+    ONE_SHOT_LAYER,
+    DUAL_FUNCTION,
+    TAP_DANCE,
+    LEADER,
+    MACRO,
+    SUPERKEY,
+];
+
+impl Keycode {
+    pub fn value(&self) -> u16 {
+        self.0
+    }
+
+    pub fn is_no_key(&self) -> bool {
+        self.0 == NO_KEY
+    }
+
+    pub fn is_transparent(&self) -> bool {
+        self.0 == TRANSPARENT
+    }
+
+    /// Macro triggered by this keycode, if it is a macro key.
+    pub fn macro_id(&self) -> Option<u8> {
+        MACRO
+            .contains(&self.0)
+            .then(|| (self.0 - MACRO.start()) as u8)
+    }
+
+    /// Plain or one-shot modifier.
+    pub fn is_modifier(&self) -> bool {
+        MODIFIER.contains(&self.0) || ONE_SHOT_MODIFIER.contains(&self.0)
+    }
+
+    /// Momentarily shifts to a layer while held, one-shot layers included.
+    pub fn is_layer_shift(&self) -> bool {
+        LAYER_SHIFT.contains(&self.0) || ONE_SHOT_LAYER.contains(&self.0)
+    }
+
+    pub fn is_layer_lock(&self) -> bool {
+        LAYER_LOCK.contains(&self.0)
+    }
+
+    /// Consumer control keys such as volume and playback, excluding mouse keys.
+    pub fn is_media(&self) -> bool {
+        CONSUMER.contains(&self.0) && !self.is_mouse()
+    }
+
+    pub fn is_mouse(&self) -> bool {
+        MOUSE.contains(&self.0)
+    }
+
+    pub fn is_macro(&self) -> bool {
+        MACRO.contains(&self.0)
+    }
+
+    /// Whether the keycode falls within a range the firmware understands.
+    pub fn is_valid(&self) -> bool {
+        self.is_no_key()
+            || self.is_transparent()
+            || VALID_RANGES.iter().any(|range| range.contains(&self.0))
+    }
+}
+
+/// Names of the HID keyboard usages starting at `A` (4).
+#[rustfmt::skip]
+const KEYBOARD_NAMES: &[&str] = &[
+    "A", "B", "C", "D", "E", "F", "G", "H", "I", "J", "K", "L", "M", "N", "O", "P", "Q", "R", "S",
+    "T", "U", "V", "W", "X", "Y", "Z", "1", "2", "3", "4", "5", "6", "7", "8", "9", "0", "Enter",
+    "Esc", "Backspace", "Tab", "Space", "-", "=", "[", "]", "\\", "#", ";", "'", "`", ",", ".", "/",
+    "CapsLock", "F1", "F2", "F3", "F4", "F5", "F6", "F7", "F8", "F9", "F10", "F11", "F12",
+    "PrintScreen", "ScrollLock", "Pause", "Insert", "Home", "PageUp", "Delete", "End", "PageDown",
+    "Right", "Left", "Down", "Up", "NumLock", "KP/", "KP*", "KP-", "KP+", "KPEnter", "KP1", "KP2",
+    "KP3", "KP4", "KP5", "KP6", "KP7", "KP8", "KP9", "KP0", "KP.", "NonUS\\", "App",
+];
+
+#[rustfmt::skip]
+const MODIFIER_NAMES: &[&str] = &[
+    "LCtrl", "LShift", "LAlt", "LGui", "RCtrl", "RShift", "RAlt", "RGui",
+];
+
+impl Keycode {
+    /// Human-readable name, falling back to the raw value for codes without a name.
+    pub fn name(&self) -> String {
+        let code = self.0;
+        let offset = |range: &RangeInclusive<u16>| code - range.start();
+
+        match code {
+            NO_KEY => "None".to_string(),
+            TRANSPARENT => "Transparent".to_string(),
+            4..=101 => KEYBOARD_NAMES[(code - 4) as usize].to_string(),
+            224..=231 => MODIFIER_NAMES[offset(&MODIFIER) as usize].to_string(),
+            _ if LAYER_LOCK.contains(&code) => format!("LockLayer({})", offset(&LAYER_LOCK)),
+            _ if LAYER_SHIFT.contains(&code) => format!("ShiftToLayer({})", offset(&LAYER_SHIFT)),
+            _ if LAYER_MOVE.contains(&code) => format!("MoveToLayer({})", offset(&LAYER_MOVE)),
+            _ if ONE_SHOT_MODIFIER.contains(&code) => {
+                format!(
+                    "OneShot({})",
+                    MODIFIER_NAMES[offset(&ONE_SHOT_MODIFIER) as usize]
+                )
+            }
+            _ if ONE_SHOT_LAYER.contains(&code) => {
+                format!("OneShotLayer({})", offset(&ONE_SHOT_LAYER))
+            }
+            _ if MACRO.contains(&code) => format!("Macro({})", offset(&MACRO)),
+            _ if SUPERKEY.contains(&code) => format!("Superkey({})", offset(&SUPERKEY)),
+            _ if MOUSE.contains(&code) => format!("Mouse({:#06x})", code),
+            _ if CONSUMER.contains(&code) => format!("Consumer({:#06x})", code),
+            _ => format!("{:#06x}", code),
+        }
+    }
+}
+
+impl From<u16> for Keycode {
+    fn from(value: u16) -> Self {
+        Self(value)
+    }
+}
+
+impl Display for Keycode {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn range_edges_are_valid() {
+        for range in VALID_RANGES {
+            assert!(Keycode(*range.start()).is_valid(), "{}", range.start());
+            assert!(Keycode(*range.end()).is_valid(), "{}", range.end());
+        }
+        assert!(Keycode(NO_KEY).is_valid());
+        assert!(Keycode(TRANSPARENT).is_valid());
+    }
+
+    #[test]
+    fn gaps_between_ranges_are_invalid() {
+        for keycode in [
+            3, 232, 255, 8192, 17151, 17155, 17407, 17418, 17449, 17460, 17491, 17502, 18431,
+            24576, 49152, 53291, 53851, 54108, 65534,
+        ] {
+            assert!(!Keycode(keycode).is_valid(), "{}", keycode);
+        }
+    }
+
+    #[test]
+    fn category_helpers_match_their_ranges() {
+        assert!(Keycode(224).is_modifier());
+        assert!(Keycode(231).is_modifier());
+        assert!(!Keycode(223).is_modifier());
+        assert!(Keycode(49160).is_modifier());
+
+        assert!(Keycode(17450).is_layer_shift());
+        assert!(Keycode(17459).is_layer_shift());
+        assert!(Keycode(49161).is_layer_shift());
+        assert!(!Keycode(17460).is_layer_shift());
+
+        assert!(Keycode(17408).is_layer_lock());
+        assert!(!Keycode(17418).is_layer_lock());
+
+        assert!(Keycode(18432).is_media());
+        assert!(!Keycode(20480).is_media());
+        assert!(Keycode(20480).is_mouse());
+        assert!(Keycode(20735).is_mouse());
+        assert!(!Keycode(20736).is_mouse());
+    }
+
+    #[test]
+    fn macro_id_covers_the_macro_range() {
+        assert_eq!(Keycode(53852).macro_id(), Some(0));
+        assert_eq!(Keycode(53915).macro_id(), Some(63));
+        assert_eq!(Keycode(53851).macro_id(), None);
+        assert_eq!(Keycode(53916).macro_id(), None);
+    }
+
+    #[test]
+    fn names_follow_the_hid_table() {
+        assert_eq!(Keycode(4).name(), "A");
+        assert_eq!(Keycode(40).name(), "Enter");
+        assert_eq!(Keycode(101).name(), "App");
+        assert_eq!(Keycode(224).name(), "LCtrl");
+        assert_eq!(Keycode(17409).name(), "LockLayer(1)");
+        assert_eq!(Keycode(53853).name(), "Macro(1)");
+        assert_eq!(Keycode(NO_KEY).name(), "None");
+        assert_eq!(Keycode(TRANSPARENT).name(), "Transparent");
+    }
+}
@@ -0,0 +1,634 @@
+use crate::command::command_response;
+use crate::devices::all_application;
+use crate::features::supports;
+use crate::keycode::{Keycode, NO_KEY, TRANSPARENT};
+use crate::layers::max_layers;
+use anyhow::{bail, Context, Result};
+use dygma_focus::hardware::{DeviceType, Grid, Hardware};
+use dygma_focus::Focus;
+use log::warn;
+use std::collections::BTreeMap;
+use std::fmt::Display;
+
+/// Flat keymap with the grid needed to address keys by layer, row and column.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Keymap {
+    pub rows: u8,
+    pub columns: u8,
+    pub keys: Vec<u16>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct KeyDiff {
+    pub layer: u8,
+    pub row: u8,
+    pub col: u8,
+    pub old: Keycode,
+    pub new: Keycode,
+}
+
+impl Display for KeyDiff {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "Layer {} [{}, {}]: {} -> {}",
+            self.layer,
+            self.row,
+            self.col,
+            self.old.name(),
+            self.new.name()
+        )
+    }
+}
+
+/// QWERTY rows for each half, as HID usages, from the number row down to the modifiers.
+#[rustfmt::skip]
+const QWERTY_LEFT: &[&[u16]] = &[
+    &[41, 30, 31, 32, 33, 34, 35],
+    &[43, 20, 26, 8, 21, 23],
+    &[57, 4, 22, 7, 9, 10],
+    &[225, 29, 27, 6, 25, 5],
+    &[224, 227, 226, 44],
+];
+
+#[rustfmt::skip]
+const QWERTY_RIGHT: &[&[u16]] = &[
+    &[36, 37, 38, 39, 45, 46, 42],
+    &[28, 24, 12, 18, 19, 47, 48, 49],
+    &[11, 13, 14, 15, 51, 52, 40],
+    &[17, 16, 54, 55, 56, 229],
+    &[44, 230, 231, 228],
+];
+
+impl Keymap {
+    /// A single layer of transparent keys, falling through to the layers below.
+    pub fn blank(grid: &Grid) -> Self {
+        Self {
+            rows: grid.rows,
+            columns: grid.columns,
+            keys: vec![TRANSPARENT; grid.rows as usize * grid.columns as usize],
+        }
+    }
+
+    /// A single QWERTY base layer for the device.
+    ///
+    /// The left half is placed from the first column and the right half ends at the last,
+    /// positions the layout doesn't fill are left empty.
+    pub fn qwerty_default(hardware: &Hardware) -> Result<Self> {
+        let grid = hardware
+            .keyboard
+            .as_ref()
+            .context("Hardware has no keyboard grid")?;
+        let columns = grid.columns as usize;
+
+        let mut keymap = Self {
+            keys: vec![NO_KEY; grid.rows as usize * columns],
+            ..Self::blank(grid)
+        };
+
+        for (row, (left, right)) in QWERTY_LEFT
+            .iter()
+            .zip(QWERTY_RIGHT)
+            .take(grid.rows as usize)
+            .enumerate()
+        {
+            let keys = &mut keymap.keys[row * columns..(row + 1) * columns];
+            let half = columns / 2;
+
+            for (key, &keycode) in keys[..half].iter_mut().zip(left.iter()) {
+                *key = keycode;
+            }
+            for (key, &keycode) in keys[half..].iter_mut().rev().zip(right.iter().rev()) {
+                *key = keycode;
+            }
+        }
+
+        Ok(keymap)
+    }
+
+    pub fn new(hardware: &Hardware, keys: Vec<u16>) -> Result<Self> {
+        let grid = hardware
+            .keyboard
+            .as_ref()
+            .context("Hardware has no keyboard grid")?;
+
+        let keymap = Self {
+            rows: grid.rows,
+            columns: grid.columns,
+            keys,
+        };
+        if !keymap.keys.len().is_multiple_of(keymap.layer_size()) {
+            bail!(
+                "Keymap length {} is not a multiple of the layer size {}",
+                keymap.keys.len(),
+                keymap.layer_size()
+            );
+        }
+
+        Ok(keymap)
+    }
+
+    pub fn layer_size(&self) -> usize {
+        self.rows as usize * self.columns as usize
+    }
+
+    pub fn layer_count(&self) -> usize {
+        self.keys.len() / self.layer_size()
+    }
+
+    pub fn layer(&self, layer: u8) -> Option<&[u16]> {
+        let start = layer as usize * self.layer_size();
+        self.keys.get(start..start + self.layer_size())
+    }
+
+    pub fn get(&self, layer: u8, row: u8, col: u8) -> Option<Keycode> {
+        if row >= self.rows || col >= self.columns {
+            return None;
+        }
+
+        self.layer(layer)
+            .map(|keys| Keycode(keys[row as usize * self.columns as usize + col as usize]))
+    }
+
+    /// Positions of every key bound to each macro, as `(layer, row, col)`.
+    ///
+    /// Macros missing from the result aren't reachable from the keymap.
+    pub fn macro_bindings(&self) -> BTreeMap<u8, Vec<(u8, u8, u8)>> {
+        let mut bindings: BTreeMap<u8, Vec<(u8, u8, u8)>> = BTreeMap::new();
+
+        for (position, &keycode) in self.keys.iter().enumerate() {
+            if let Some(macro_id) = Keycode(keycode).macro_id() {
+                let layer = position / self.layer_size();
+                let key = position % self.layer_size();
+                bindings.entry(macro_id).or_default().push((
+                    layer as u8,
+                    (key / self.columns as usize) as u8,
+                    (key % self.columns as usize) as u8,
+                ));
+            }
+        }
+
+        bindings
+    }
+
+    /// Renders every layer as an aligned grid of key names, for printing or sharing.
+    ///
+    /// Transparent keys are shown as `___` and empty keys as `---`, keycodes without a name
+    /// fall back to their raw value.
+    pub fn render_table(&self) -> String {
+        let name = |keycode: Keycode| match keycode.0 {
+            TRANSPARENT => "___".to_string(),
+            NO_KEY => "---".to_string(),
+            _ => keycode.name(),
+        };
+
+        let mut output = String::new();
+
+        for layer in 0..self.layer_count() as u8 {
+            let Some(keys) = self.layer(layer) else {
+                break;
+            };
+            let names = keys
+                .iter()
+                .map(|&keycode| name(Keycode(keycode)))
+                .collect::<Vec<_>>();
+            let width = names.iter().map(String::len).max().unwrap_or(0);
+
+            output.push_str(&format!("Layer {}\n", layer));
+            for row in names.chunks(self.columns as usize) {
+                let line = row
+                    .iter()
+                    .map(|name| format!("{:<width$}", name, width = width))
+                    .collect::<Vec<_>>()
+                    .join(" | ");
+                output.push_str(line.trim_end());
+                output.push('\n');
+            }
+            output.push('\n');
+        }
+
+        output
+    }
+
+    /// Every key that differs between the two keymaps.
+    ///
+    /// Errors when the grids or layer counts differ, since keys would have no counterpart.
+    pub fn diff(&self, other: &Keymap) -> Result<Vec<KeyDiff>> {
+        if (self.rows, self.columns, self.layer_count())
+            != (other.rows, other.columns, other.layer_count())
+        {
+            bail!(
+                "Can't diff a {}x{} keymap with {} layers against a {}x{} keymap with {} layers",
+                self.rows,
+                self.columns,
+                self.layer_count(),
+                other.rows,
+                other.columns,
+                other.layer_count()
+            );
+        }
+
+        let mut diffs = Vec::new();
+
+        for layer in 0..self.layer_count() as u8 {
+            for row in 0..self.rows {
+                for col in 0..self.columns {
+                    let (Some(old), Some(new)) =
+                        (self.get(layer, row, col), other.get(layer, row, col))
+                    else {
+                        continue;
+                    };
+                    if old != new {
+                        diffs.push(KeyDiff {
+                            layer,
+                            row,
+                            col,
+                            old,
+                            new,
+                        });
+                    }
+                }
+            }
+        }
+
+        Ok(diffs)
+    }
+}
+
+/// Returns the position and value of every keycode the firmware would not understand.
+pub fn invalid_keycodes(data: &[u16]) -> Vec<(usize, u16)> {
+    data.iter()
+        .enumerate()
+        .filter(|(_, &keycode)| !Keycode(keycode).is_valid())
+        .map(|(position, &keycode)| (position, keycode))
+        .collect()
+}
+
+/// Validates every keycode before writing the custom keymap.
+///
+/// Use `Focus::keymap_custom_set` to write the keymap without validation.
+#[tracing::instrument(skip(focus, data))]
+pub async fn keymap_custom_set_checked(focus: &mut Focus, data: &[u16]) -> Result<()> {
+    let invalid = invalid_keycodes(data);
+    if !invalid.is_empty() {
+        let positions = invalid
+            .iter()
+            .map(|(position, keycode)| format!("{}: {}", position, keycode))
+            .collect::<Vec<_>>()
+            .join(", ");
+        bail!("Keymap contains invalid keycodes at [{}]", positions);
+    }
+
+    focus.keymap_custom_set(data).await?;
+
+    Ok(())
+}
+
+/// Reads the keymap the firmware actually uses for its layer numbering.
+///
+/// With `keymap.onlyCustom` enabled only the custom layers exist.
+/// Otherwise the default layers come first and the custom layers are numbered after them,
+/// so layer `n` of the result is layer `n` as seen by `layer.moveTo` and friends.
+#[tracing::instrument(skip(focus))]
+pub async fn effective_keymap(focus: &mut Focus) -> Result<Vec<u16>> {
+    let custom = focus.keymap_custom_get().await?;
+
+    if focus.keymap_only_custom_get().await? {
+        return Ok(custom);
+    }
+
+    let mut keymap = focus.keymap_default_get().await?;
+    keymap.extend(custom);
+
+    Ok(keymap)
+}
+
+/// Number of keys in a single keymap layer.
+pub fn keys_per_layer(hardware: &Hardware) -> Result<usize> {
+    let grid = hardware
+        .keyboard
+        .as_ref()
+        .context("Hardware has no keyboard grid")?;
+
+    Ok(grid.rows as usize * grid.columns as usize)
+}
+
+const LAYOUT: &str = "hardware.layout";
+
+/// The table entry for the physical layout the firmware reports, such as `ISO` on a device
+/// detected as a Raise ANSI, since both share USB IDs.
+///
+/// Only layout variants (ANSI and ISO) are swapped, other devices keep their own entry.
+fn layout_variant(hardware: &Hardware, layout: &str) -> Option<&'static Hardware> {
+    if !matches!(
+        hardware.info.device_type,
+        DeviceType::ANSI | DeviceType::ISO
+    ) {
+        return None;
+    }
+
+    all_application().into_iter().find(|candidate| {
+        candidate.info.product == hardware.info.product
+            && candidate
+                .info
+                .display_name
+                .strip_suffix(layout.trim())
+                .is_some_and(|name| name.ends_with(' '))
+    })
+}
+
+/// Keyboard grid as reported by the device, checked against the static hardware table.
+///
+/// `hardware.layout` only names the physical layout, so when the firmware lists it the
+/// table entry for that layout is used. The number of keys per layer is derived from the
+/// custom keymap length and the layer count. When it disagrees with the table a warning
+/// is logged and the rows from the table are kept, with the columns recalculated from the
+/// live key count.
+#[tracing::instrument(skip(focus, hardware))]
+pub async fn grid(focus: &mut Focus, hardware: &Hardware) -> Result<Grid> {
+    let mut hardware = hardware;
+    if supports(focus, LAYOUT).await? {
+        let layout = command_response(focus, LAYOUT).await?;
+        if let Some(variant) = layout_variant(hardware, &layout) {
+            hardware = variant;
+        }
+    }
+
+    let expected = hardware
+        .keyboard
+        .as_ref()
+        .context("Hardware has no keyboard grid")?;
+
+    let keys = focus.keymap_custom_get().await?.len();
+    let layers = max_layers(focus).await? as usize;
+    if keys % layers != 0 {
+        bail!(
+            "Keymap length {} is not a multiple of {} layers",
+            keys,
+            layers
+        );
+    }
+
+    let live_keys = keys / layers;
+    let expected_keys = expected.rows as usize * expected.columns as usize;
+    if live_keys == expected_keys {
+        return Ok(*expected);
+    }
+
+    warn!(
+        "Device reports {} keys per layer, the {} table expects {}x{}",
+        live_keys, hardware.info.display_name, expected.rows, expected.columns
+    );
+    if !live_keys.is_multiple_of(expected.rows as usize) {
+        bail!(
+            "Device reports {} keys per layer, which doesn't fit {} rows",
+            live_keys,
+            expected.rows
+        );
+    }
+
+    Ok(Grid {
+        rows: expected.rows,
+        columns: (live_keys / expected.rows as usize) as u8,
+    })
+}
+
+/// Position of a key in the flat keymap.
+pub fn key_position(hardware: &Hardware, layer: u8, row: u8, col: u8) -> Result<usize> {
+    let grid = hardware
+        .keyboard
+        .as_ref()
+        .context("Hardware has no keyboard grid")?;
+    if row >= grid.rows || col >= grid.columns {
+        bail!(
+            "Key {}x{} is outside the {}x{} grid",
+            row,
+            col,
+            grid.rows,
+            grid.columns
+        );
+    }
+
+    Ok(layer as usize * keys_per_layer(hardware)?
+        + row as usize * grid.columns as usize
+        + col as usize)
+}
+
+/// A previewed key change that can be reverted.
+///
+/// The firmware has no RAM-only key override, every keymap write replaces the custom keymap
+/// in EEPROM. Previewing writes the changed keymap and keeps the original in memory so
+/// [`KeyPreview::revert`] can restore it with a single write.
+#[derive(Debug, Clone)]
+pub struct KeyPreview {
+    original: Vec<u16>,
+}
+
+impl KeyPreview {
+    /// Restores the keymap as it was before the preview.
+    pub async fn revert(self, focus: &mut Focus) -> Result<()> {
+        focus.keymap_custom_set(&self.original).await?;
+
+        Ok(())
+    }
+
+    /// Keeps the previewed change, the keymap is already on the device.
+    pub fn commit(self) {}
+}
+
+/// Writes a single key change, returning a handle to revert it.
+#[tracing::instrument(skip(focus, hardware))]
+pub async fn preview_key(
+    focus: &mut Focus,
+    hardware: &Hardware,
+    layer: u8,
+    row: u8,
+    col: u8,
+    keycode: u16,
+) -> Result<KeyPreview> {
+    let position = key_position(hardware, layer, row, col)?;
+
+    let original = focus.keymap_custom_get().await?;
+    if position >= original.len() {
+        bail!("Layer {} is out of range", layer);
+    }
+
+    let mut keymap = original.clone();
+    keymap[position] = keycode;
+    focus.keymap_custom_set(&keymap).await?;
+
+    Ok(KeyPreview { original })
+}
+
+/// Writes the default keymap, refusing when it would have no effect.
+///
+/// The default layers are only used while `keymap.onlyCustom` is disabled,
+/// with it enabled the write succeeds but nothing changes on the keyboard.
+#[tracing::instrument(skip(focus, data))]
+pub async fn keymap_default_set_checked(focus: &mut Focus, data: &[u16]) -> Result<()> {
+    if focus.keymap_only_custom_get().await? {
+        bail!("Default layers are unused while keymap.onlyCustom is enabled, use configure_default_layers");
+    }
+
+    focus.keymap_default_set(data).await?;
+
+    Ok(())
+}
+
+/// Writes the default keymap and the `keymap.onlyCustom` flag together.
+///
+/// Pass `only_custom: false` for the default layers to be used.
+#[tracing::instrument(skip(focus, keymap))]
+pub async fn configure_default_layers(
+    focus: &mut Focus,
+    keymap: &[u16],
+    only_custom: bool,
+) -> Result<()> {
+    focus.keymap_default_set(keymap).await?;
+    focus.keymap_only_custom_set(only_custom).await?;
+
+    Ok(())
+}
+
+/// Copies the listed layers from `source` into the custom keymap, keeping every other layer.
+///
+/// Every layer index is checked against both keymaps before anything is written.
+#[tracing::instrument(skip(focus, source))]
+pub async fn apply_layers(focus: &mut Focus, source: &Keymap, layers: &[u8]) -> Result<()> {
+    let mut current = Keymap {
+        rows: source.rows,
+        columns: source.columns,
+        keys: focus.keymap_custom_get().await?,
+    };
+    if !current.keys.len().is_multiple_of(current.layer_size()) {
+        bail!(
+            "Device keymap length {} doesn't match the source layer size {}",
+            current.keys.len(),
+            current.layer_size()
+        );
+    }
+
+    for &layer in layers {
+        if layer as usize >= source.layer_count() {
+            bail!("Layer {} is missing from the source keymap", layer);
+        }
+        if layer as usize >= current.layer_count() {
+            bail!("Layer {} is out of range", layer);
+        }
+    }
+
+    let layer_size = current.layer_size();
+    for &layer in layers {
+        let start = layer as usize * layer_size;
+        current.keys[start..start + layer_size]
+            .copy_from_slice(&source.keys[start..start + layer_size]);
+    }
+
+    focus.keymap_custom_set(&current.keys).await?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use dygma_focus::hardware::types::hardware_physical::{
+        DEFY_WIRED, DEFY_WIRED_BOOTLOADER, DEVICES_PHYSICAL, RAISE_ANSI, RAISE_ISO,
+    };
+
+    #[test]
+    fn diff_lists_changed_keys() {
+        let grid = Grid {
+            rows: 2,
+            columns: 2,
+        };
+        let old = Keymap::blank(&grid);
+        let mut new = old.clone();
+        new.keys[3] = 4;
+
+        assert_eq!(
+            old.diff(&new).unwrap(),
+            vec![KeyDiff {
+                layer: 0,
+                row: 1,
+                col: 1,
+                old: Keycode(TRANSPARENT),
+                new: Keycode(4),
+            }]
+        );
+    }
+
+    #[test]
+    fn diff_refuses_mismatched_grids() {
+        let small = Keymap::blank(&Grid {
+            rows: 2,
+            columns: 2,
+        });
+        let wide = Keymap::blank(&Grid {
+            rows: 2,
+            columns: 3,
+        });
+        let mut two_layers = small.clone();
+        two_layers.keys.extend(small.keys.clone());
+
+        assert!(small.diff(&wide).is_err());
+        assert!(small.diff(&two_layers).is_err());
+    }
+
+    #[test]
+    fn layout_selects_the_matching_raise() {
+        let iso = layout_variant(&RAISE_ANSI, "ISO\r\n").unwrap();
+        assert_eq!(iso.info.display_name, RAISE_ISO.info.display_name);
+
+        let ansi = layout_variant(&RAISE_ANSI, "ANSI").unwrap();
+        assert_eq!(ansi.info.display_name, RAISE_ANSI.info.display_name);
+    }
+
+    #[test]
+    fn layout_keeps_devices_without_variants() {
+        assert!(layout_variant(&DEFY_WIRED, "ISO").is_none());
+    }
+
+    #[test]
+    fn blank_is_one_transparent_layer() {
+        let grid = Grid {
+            rows: 5,
+            columns: 16,
+        };
+        let keymap = Keymap::blank(&grid);
+
+        assert_eq!(keymap.layer_size(), 80);
+        assert_eq!(keymap.layer_count(), 1);
+        assert!(keymap.keys.iter().all(|&key| key == TRANSPARENT));
+    }
+
+    #[test]
+    fn qwerty_default_places_both_halves() {
+        let keymap = Keymap::qwerty_default(&DEFY_WIRED).unwrap();
+        let last = keymap.columns - 1;
+
+        assert_eq!(keymap.layer_count(), 1);
+        assert_eq!(keymap.get(0, 0, 0), Some(Keycode(41)));
+        assert_eq!(keymap.get(0, 0, last), Some(Keycode(42)));
+        assert_eq!(keymap.get(0, 2, 1), Some(Keycode(4)));
+        assert_eq!(keymap.get(0, 4, last), Some(Keycode(228)));
+    }
+
+    #[test]
+    fn qwerty_default_fits_every_device() {
+        for hardware in DEVICES_PHYSICAL
+            .iter()
+            .filter(|hardware| !hardware.bootloader)
+        {
+            let keymap = Keymap::qwerty_default(hardware).unwrap();
+
+            assert!(!keymap.keys.contains(&TRANSPARENT));
+            assert!(keymap.keys.iter().any(|&key| key != NO_KEY));
+        }
+    }
+
+    #[test]
+    fn qwerty_default_needs_a_keyboard_grid() {
+        assert!(Keymap::qwerty_default(&DEFY_WIRED_BOOTLOADER).is_err());
+    }
+}
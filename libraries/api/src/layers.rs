@@ -0,0 +1,106 @@
+use crate::features::supports;
+use anyhow::{bail, Result};
+use dygma_focus::{Focus, MAX_LAYERS};
+
+/// Layer count to assume when the firmware doesn't report one.
+///
+/// [`MAX_LAYERS`] is the highest layer index dygma_focus accepts, not a count.
+const FALLBACK_LAYERS: u8 = MAX_LAYERS + 1;
+
+/// Number of layers the device supports, from the width of `layer.state`.
+///
+/// Falls back to [`FALLBACK_LAYERS`] when the firmware doesn't report the layer state.
+#[tracing::instrument(skip(focus))]
+pub async fn max_layers(focus: &mut Focus) -> Result<u8> {
+    if !supports(focus, "layer.state").await? {
+        return Ok(FALLBACK_LAYERS);
+    }
+
+    let state = focus.layer_state().await?;
+    if state.is_empty() {
+        return Ok(FALLBACK_LAYERS);
+    }
+
+    Ok(u8::try_from(state.len()).unwrap_or(u8::MAX))
+}
+
+fn validate_layer(layer: u8, max_layers: u8) -> Result<()> {
+    if layer >= max_layers {
+        bail!("Layer {} is out of range, max is {}", layer, max_layers - 1);
+    }
+
+    Ok(())
+}
+
+/// Highest active layer according to `layer.state`.
+pub async fn active_layer(focus: &mut Focus) -> Result<u8> {
+    let state = focus.layer_state().await?;
+
+    Ok(state.iter().rposition(|&active| active).unwrap_or(0) as u8)
+}
+
+/// What a layer indicator needs to display.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LayerStatus {
+    /// Layer the keyboard starts on.
+    pub default_layer: u8,
+    /// Every active layer, lowest first.
+    pub active_layers: Vec<u8>,
+    /// Highest active layer, the one keys are looked up in first.
+    pub top_layer: u8,
+    /// Depth of the layer switch history, `None` when the firmware doesn't report it.
+    ///
+    /// No command listed by current firmware exposes the history, so this is always `None`
+    /// for now.
+    pub history_depth: Option<usize>,
+}
+
+/// Reads the default layer and the active layers together.
+#[tracing::instrument(skip(focus))]
+pub async fn layer_status(focus: &mut Focus) -> Result<LayerStatus> {
+    let default_layer = focus.settings_default_layer_get().await?;
+    let state = focus.layer_state().await?;
+
+    let active_layers = state
+        .iter()
+        .enumerate()
+        .filter(|(_, &active)| active)
+        .map(|(layer, _)| layer as u8)
+        .collect::<Vec<_>>();
+    let top_layer = active_layers.last().copied().unwrap_or(0);
+
+    Ok(LayerStatus {
+        default_layer,
+        active_layers,
+        top_layer,
+        history_depth: None,
+    })
+}
+
+/// Switches to whichever of the two layers isn't currently active, returning the new layer.
+#[tracing::instrument(skip(focus))]
+pub async fn toggle_layer(focus: &mut Focus, a: u8, b: u8) -> Result<u8> {
+    let max_layers = max_layers(focus).await?;
+    validate_layer(a, max_layers)?;
+    validate_layer(b, max_layers)?;
+
+    let target = if active_layer(focus).await? == a {
+        b
+    } else {
+        a
+    };
+    focus.layer_move_to(target).await?;
+
+    Ok(target)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn fallback_accepts_the_highest_layer_index() {
+        assert!(validate_layer(MAX_LAYERS, FALLBACK_LAYERS).is_ok());
+        assert!(validate_layer(MAX_LAYERS + 1, FALLBACK_LAYERS).is_err());
+    }
+}
@@ -0,0 +1,83 @@
+use crate::color::{palette_get, palette_set, ThemePalette};
+use crate::keycode::{NO_KEY, TRANSPARENT};
+use anyhow::Result;
+use dygma_focus::hardware::Hardware;
+use dygma_focus::Focus;
+use log::info;
+
+/// Everything that defines a layout, without the device settings.
+#[derive(Debug, Clone)]
+pub struct LayoutSnapshot {
+    pub keymap_custom: Vec<u16>,
+    pub keymap_default: Vec<u16>,
+    pub colormap: Vec<u8>,
+    pub palette: ThemePalette,
+    pub macros: Vec<u8>,
+    pub superkeys: Vec<u16>,
+}
+
+/// Reads the keymaps, colors, macros and superkeys in one pass.
+#[tracing::instrument(skip(focus, hardware))]
+pub async fn layout_snapshot(focus: &mut Focus, hardware: &Hardware) -> Result<LayoutSnapshot> {
+    Ok(LayoutSnapshot {
+        keymap_custom: focus.keymap_custom_get().await?,
+        keymap_default: focus.keymap_default_get().await?,
+        colormap: focus.color_map_get().await?,
+        palette: palette_get(focus, hardware).await?,
+        macros: focus.macros_map_get().await?,
+        superkeys: focus.superkeys_map_get().await?,
+    })
+}
+
+/// Writes a snapshot in dependency order.
+///
+/// The palette is written before the colormap that indexes into it,
+/// and macros and superkeys before the keymaps that reference them.
+#[tracing::instrument(skip(focus, snapshot))]
+pub async fn apply_layout_snapshot(focus: &mut Focus, snapshot: &LayoutSnapshot) -> Result<()> {
+    info!("Applying layout snapshot");
+
+    palette_set(focus, &snapshot.palette).await?;
+    focus.color_map_set(&snapshot.colormap).await?;
+    focus.macros_map_set(&snapshot.macros).await?;
+    focus.superkeys_map_set(&snapshot.superkeys).await?;
+    focus.keymap_default_set(&snapshot.keymap_default).await?;
+    focus.keymap_custom_set(&snapshot.keymap_custom).await?;
+
+    Ok(())
+}
+
+fn is_blank_key(keycode: u16) -> bool {
+    keycode == NO_KEY || keycode == TRANSPARENT
+}
+
+/// Whether the user changed anything a restore or reset would overwrite.
+///
+/// - With `keymap.onlyCustom` enabled the custom layers replace the defaults, so they count
+///   as customized when they differ from the default layers. Custom layers beyond the
+///   default ones count when they hold any key.
+/// - Otherwise the custom layers are added after the defaults and count as customized as
+///   soon as they hold any key.
+/// - The colormap counts as customized when any LED uses a palette index other than `0`.
+#[tracing::instrument(skip(focus))]
+pub async fn has_customizations(focus: &mut Focus) -> Result<bool> {
+    let custom = focus.keymap_custom_get().await?;
+
+    let keymap_customized = if focus.keymap_only_custom_get().await? {
+        let default = focus.keymap_default_get().await?;
+        custom
+            .iter()
+            .enumerate()
+            .any(|(position, &keycode)| match default.get(position) {
+                Some(&default) => keycode != default,
+                None => !is_blank_key(keycode),
+            })
+    } else {
+        custom.iter().any(|&keycode| !is_blank_key(keycode))
+    };
+    if keymap_customized {
+        return Ok(true);
+    }
+
+    Ok(focus.color_map_get().await?.iter().any(|&index| index != 0))
+}
@@ -0,0 +1,337 @@
+use crate::color::leds_per_layer;
+use crate::features::{supported_commands, supports};
+use anyhow::{bail, Context, Result};
+use dygma_focus::color::RGB;
+use dygma_focus::hardware::{Grid, Hardware};
+use dygma_focus::prelude::*;
+use std::fmt::Display;
+use std::time::Duration;
+
+/// LED colors can't be read while a dynamic effect is running.
+#[derive(Debug)]
+pub struct LedStateUnavailable {
+    pub mode: LedMode,
+}
+
+impl Display for LedStateUnavailable {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "LED colors are unavailable in {:?} mode, switch to Layer to read them",
+            self.mode
+        )
+    }
+}
+
+impl std::error::Error for LedStateUnavailable {}
+
+/// Stored LED theme along with whether the current mode displays it.
+#[derive(Debug, Clone)]
+pub struct EffectiveLedState {
+    pub mode: LedMode,
+    pub theme: Vec<RGB>,
+    /// `false` while a dynamic effect is running, the theme only shows in `Layer` mode.
+    pub theme_displayed: bool,
+}
+
+/// Reads the LED mode and theme together.
+///
+/// `led.theme` always returns the stored theme, even when a dynamic effect such as a
+/// rainbow overrides what the keyboard shows.
+#[tracing::instrument(skip(focus))]
+pub async fn effective_led_state(focus: &mut Focus) -> Result<EffectiveLedState> {
+    let mode = focus.led_mode_get().await?;
+    let theme = focus.led_theme_get().await?;
+    let theme_displayed = matches!(mode, LedMode::Layer);
+
+    Ok(EffectiveLedState {
+        mode,
+        theme,
+        theme_displayed,
+    })
+}
+
+/// Reads the color of a single LED, checking the LED mode first.
+///
+/// Returns [`LedStateUnavailable`] when a dynamic effect is running, instead of the
+/// parse error `led.at` produces in that state.
+#[tracing::instrument(skip(focus))]
+pub async fn led_at_get(focus: &mut Focus, led: u8) -> Result<RGB> {
+    let mode = focus.led_mode_get().await?;
+    if !matches!(mode, LedMode::Layer) {
+        return Err(LedStateUnavailable { mode }.into());
+    }
+
+    focus.led_at_get(led).await
+}
+
+/// Reads the LED fade, `None` when the firmware doesn't support it.
+#[tracing::instrument(skip(focus))]
+pub async fn led_fade_get(focus: &mut Focus) -> Result<Option<u16>> {
+    if !supports(focus, "led.fade").await? {
+        return Ok(None);
+    }
+
+    Ok(Some(focus.led_fade_get().await?))
+}
+
+/// Sets the fade speed between LED colors, 0 disables fading.
+///
+/// Only Defy firmware implements `led.fade`.
+#[tracing::instrument(skip(focus))]
+pub async fn led_fade_set(focus: &mut Focus, fade: u16) -> Result<()> {
+    if !supports(focus, "led.fade").await? {
+        bail!("Firmware does not support led.fade");
+    }
+
+    focus.led_fade_set(fade).await?;
+
+    Ok(())
+}
+
+const BRIGHTNESS_UNDERGLOW: &str = "led.brightnessUG";
+const BRIGHTNESS_WIRELESS_TOP: &str = "led.brightness.wireless";
+const BRIGHTNESS_WIRELESS_UNDERGLOW: &str = "led.brightnessUG.wireless";
+
+/// Every LED brightness, `None` for the ones the device doesn't have.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BrightnessSet {
+    pub top: u8,
+    pub underglow: Option<u8>,
+    pub wireless_top: Option<u8>,
+    pub wireless_underglow: Option<u8>,
+}
+
+impl Display for BrightnessSet {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let value = |value: Option<u8>| {
+            value
+                .map(|value| value.to_string())
+                .unwrap_or_else(|| "-".to_string())
+        };
+
+        write!(
+            f,
+            "Top: {}, Underglow: {}, Wireless top: {}, Wireless underglow: {}",
+            self.top,
+            value(self.underglow),
+            value(self.wireless_top),
+            value(self.wireless_underglow)
+        )
+    }
+}
+
+/// Reads every LED brightness the firmware supports.
+#[tracing::instrument(skip(focus))]
+pub async fn brightness_all(focus: &mut Focus) -> Result<BrightnessSet> {
+    let commands = supported_commands(focus).await?;
+
+    let mut brightness = BrightnessSet {
+        top: focus.led_brightness_top_get().await?,
+        underglow: None,
+        wireless_top: None,
+        wireless_underglow: None,
+    };
+    if commands.contains(BRIGHTNESS_UNDERGLOW) {
+        brightness.underglow = Some(focus.led_brightness_underglow_get().await?);
+    }
+    if commands.contains(BRIGHTNESS_WIRELESS_TOP) {
+        brightness.wireless_top = Some(focus.led_brightness_wireless_top_get().await?);
+    }
+    if commands.contains(BRIGHTNESS_WIRELESS_UNDERGLOW) {
+        brightness.wireless_underglow = Some(focus.led_brightness_wireless_underglow_get().await?);
+    }
+
+    Ok(brightness)
+}
+
+/// Writes every brightness that is `Some`, refusing values the firmware doesn't support.
+#[tracing::instrument(skip(focus))]
+pub async fn brightness_all_set(focus: &mut Focus, brightness: &BrightnessSet) -> Result<()> {
+    let commands = supported_commands(focus).await?;
+    for (command, value) in [
+        (BRIGHTNESS_UNDERGLOW, brightness.underglow),
+        (BRIGHTNESS_WIRELESS_TOP, brightness.wireless_top),
+        (BRIGHTNESS_WIRELESS_UNDERGLOW, brightness.wireless_underglow),
+    ] {
+        if value.is_some() && !commands.contains(command) {
+            bail!("Firmware does not support {}", command);
+        }
+    }
+
+    focus.led_brightness_top_set(brightness.top).await?;
+    if let Some(value) = brightness.underglow {
+        focus.led_brightness_underglow_set(value).await?;
+    }
+    if let Some(value) = brightness.wireless_top {
+        focus.led_brightness_wireless_top_set(value).await?;
+    }
+    if let Some(value) = brightness.wireless_underglow {
+        focus.led_brightness_wireless_underglow_set(value).await?;
+    }
+
+    Ok(())
+}
+
+/// LED theme with each color tied to its physical position, key LEDs then underglow.
+#[derive(Debug, Clone)]
+pub struct LedTheme {
+    keyboard: Grid,
+    underglow: Option<Grid>,
+    colors: Vec<RGB>,
+}
+
+impl LedTheme {
+    pub fn new(hardware: &Hardware, colors: Vec<RGB>) -> Result<Self> {
+        let keyboard = hardware.keyboard.context("Hardware has no keyboard grid")?;
+
+        let expected = leds_per_layer(hardware);
+        if colors.len() != expected {
+            bail!(
+                "LED theme has {} colors, expected {}",
+                colors.len(),
+                expected
+            );
+        }
+
+        Ok(Self {
+            keyboard,
+            underglow: hardware.keyboard_underglow,
+            colors,
+        })
+    }
+
+    /// Reads the theme from the device.
+    pub async fn get(focus: &mut Focus, hardware: &Hardware) -> Result<Self> {
+        Self::new(hardware, focus.led_theme_get().await?)
+    }
+
+    /// Writes the theme to the device.
+    pub async fn set(&self, focus: &mut Focus) -> Result<()> {
+        focus.led_theme_set(&self.colors).await?;
+
+        Ok(())
+    }
+
+    fn key_index(&self, row: u8, col: u8) -> Option<usize> {
+        (row < self.keyboard.rows && col < self.keyboard.columns)
+            .then(|| row as usize * self.keyboard.columns as usize + col as usize)
+    }
+
+    fn underglow_index(&self, row: u8, col: u8) -> Option<usize> {
+        let grid = self.underglow.as_ref()?;
+        let keys = self.keyboard.rows as usize * self.keyboard.columns as usize;
+
+        (row < grid.rows && col < grid.columns)
+            .then(|| keys + row as usize * grid.columns as usize + col as usize)
+    }
+
+    /// Color of the key LED at the position.
+    pub fn color_at(&self, row: u8, col: u8) -> Option<&RGB> {
+        self.key_index(row, col).map(|index| &self.colors[index])
+    }
+
+    pub fn set_color_at(&mut self, row: u8, col: u8, color: RGB) -> Result<()> {
+        let index = self
+            .key_index(row, col)
+            .with_context(|| format!("Key LED {}x{} is out of range", row, col))?;
+        self.colors[index] = color;
+
+        Ok(())
+    }
+
+    /// Color of the underglow LED at the position.
+    pub fn underglow_at(&self, row: u8, col: u8) -> Option<&RGB> {
+        self.underglow_index(row, col)
+            .map(|index| &self.colors[index])
+    }
+
+    pub fn set_underglow_at(&mut self, row: u8, col: u8, color: RGB) -> Result<()> {
+        let index = self
+            .underglow_index(row, col)
+            .with_context(|| format!("Underglow LED {}x{} is out of range", row, col))?;
+        self.colors[index] = color;
+
+        Ok(())
+    }
+
+    pub fn colors(&self) -> &[RGB] {
+        &self.colors
+    }
+}
+
+impl From<LedTheme> for Vec<RGB> {
+    fn from(theme: LedTheme) -> Self {
+        theme.colors
+    }
+}
+
+const LED_OFF: RGB = RGB { r: 0, g: 0, b: 0 };
+
+/// Lights one LED at a time by writing the whole theme.
+///
+/// `led.at` only addresses the first 256 LEDs, the theme covers every LED on the device.
+async fn walk_leds(focus: &mut Focus, leds: usize, color: &RGB, delay: Duration) -> Result<()> {
+    for led in 0..leds {
+        let theme = (0..leds)
+            .map(|index| if index == led { *color } else { LED_OFF })
+            .collect::<Vec<_>>();
+        focus.led_theme_set(&theme).await?;
+        tokio::time::sleep(delay).await;
+    }
+
+    focus.led_theme_set(&vec![LED_OFF; leds]).await?;
+
+    Ok(())
+}
+
+/// Runs the test, then restores the LED mode and theme even when the test failed.
+async fn run_led_test(
+    focus: &mut Focus,
+    hardware: &Hardware,
+    colors: &[RGB],
+    delay: Duration,
+) -> Result<()> {
+    let leds = leds_per_layer(hardware);
+    let mode = focus.led_mode_get().await?;
+    let theme = focus.led_theme_get().await?;
+
+    focus.led_mode_set(LedMode::Layer).await?;
+    let mut result = Ok(());
+    for color in colors {
+        result = walk_leds(focus, leds, color, delay).await;
+        if result.is_err() {
+            break;
+        }
+    }
+
+    focus.led_theme_set(&theme).await?;
+    focus.led_mode_set(mode).await?;
+
+    result
+}
+
+/// Lights each LED in turn with the color, to spot dead LEDs.
+///
+/// The LED mode and theme are restored afterwards.
+#[tracing::instrument(skip(focus, hardware))]
+pub async fn led_test(
+    focus: &mut Focus,
+    hardware: &Hardware,
+    color: &RGB,
+    delay: Duration,
+) -> Result<()> {
+    run_led_test(focus, hardware, std::slice::from_ref(color), delay).await
+}
+
+/// Walks red, green and blue across every LED, catching LEDs with a dead channel.
+#[tracing::instrument(skip(focus, hardware))]
+pub async fn led_test_all(focus: &mut Focus, hardware: &Hardware, delay: Duration) -> Result<()> {
+    let colors = [
+        RGB { r: 255, g: 0, b: 0 },
+        RGB { r: 0, g: 255, b: 0 },
+        RGB { r: 0, g: 0, b: 255 },
+    ];
+
+    run_led_test(focus, hardware, &colors, delay).await
+}
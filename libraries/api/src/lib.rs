@@ -1,4 +1,26 @@
 extern crate core;
 
+#[cfg(feature = "bench")]
+pub mod bench;
+pub mod color;
+pub mod command;
+pub mod devices;
+pub mod diagnostics;
+pub mod eeprom;
+pub mod features;
 pub mod firmware_downloader;
 pub mod flash;
+pub mod keycode;
+pub mod keymap;
+pub mod layers;
+pub mod layout;
+pub mod led;
+pub mod macros;
+pub mod mouse;
+#[cfg(feature = "schema")]
+pub mod schema;
+pub mod settings;
+pub mod sides;
+pub mod superkeys;
+pub mod update;
+pub mod wireless;
@@ -0,0 +1,48 @@
+use anyhow::{bail, Result};
+use dygma_focus::Focus;
+
+/// Checks the macro map fits in the device's macro memory before writing it.
+///
+/// Firmware truncates or rejects oversized maps, leaving macros partially written.
+#[tracing::instrument(skip(focus, data))]
+pub async fn macros_map_set_checked(focus: &mut Focus, data: &[u8]) -> Result<()> {
+    let memory = focus.macros_memory().await? as usize;
+    validate_size(data, memory)?;
+
+    focus.macros_map_set(data).await?;
+
+    Ok(())
+}
+
+fn validate_size(data: &[u8], memory: usize) -> Result<()> {
+    if data.len() > memory {
+        bail!(
+            "Macro map is {} bytes, the device only has {} bytes of macro memory",
+            data.len(),
+            memory
+        );
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn map_fitting_memory_is_accepted() {
+        assert!(validate_size(&[0; 4], 8).is_ok());
+        assert!(validate_size(&[0; 8], 8).is_ok());
+    }
+
+    #[test]
+    fn oversized_map_is_rejected() {
+        let error = validate_size(&[0; 9], 8).unwrap_err();
+
+        assert_eq!(
+            error.to_string(),
+            "Macro map is 9 bytes, the device only has 8 bytes of macro memory"
+        );
+    }
+}
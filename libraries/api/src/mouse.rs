@@ -0,0 +1,119 @@
+use crate::settings::SettingsBuilder;
+use anyhow::{bail, Result};
+use dygma_focus::prelude::*;
+use std::time::Duration;
+
+/// Highest value the firmware accepts for the mouse speeds and the speed limit.
+pub const MAX_MOUSE_SPEED: u8 = 127;
+/// Mouse delays are stored as 16-bit millisecond values.
+pub const MAX_MOUSE_DELAY: Duration = Duration::from_millis(u16::MAX as u64);
+
+/// Mouse key settings, read and written as a unit.
+///
+/// The individual `Focus::mouse_*` methods remain available for changing a single value.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MouseConfig {
+    pub speed: u8,
+    pub delay: Duration,
+    pub acceleration_speed: u8,
+    pub acceleration_delay: Duration,
+    pub wheel_speed: u8,
+    pub wheel_delay: Duration,
+    pub speed_limit: u8,
+}
+
+impl MouseConfig {
+    pub fn validate(&self) -> Result<()> {
+        for (name, speed) in [
+            ("speed", self.speed),
+            ("acceleration speed", self.acceleration_speed),
+            ("wheel speed", self.wheel_speed),
+            ("speed limit", self.speed_limit),
+        ] {
+            if speed > MAX_MOUSE_SPEED {
+                bail!(
+                    "Mouse {} {} exceeds the maximum of {}",
+                    name,
+                    speed,
+                    MAX_MOUSE_SPEED
+                );
+            }
+        }
+
+        for (name, delay) in [
+            ("delay", self.delay),
+            ("acceleration delay", self.acceleration_delay),
+            ("wheel delay", self.wheel_delay),
+        ] {
+            if delay > MAX_MOUSE_DELAY {
+                bail!(
+                    "Mouse {} of {}ms exceeds the maximum of {}ms",
+                    name,
+                    delay.as_millis(),
+                    MAX_MOUSE_DELAY.as_millis()
+                );
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Overrides the mouse fields of the builder, for writing alongside other settings.
+    pub fn apply_to(&self, builder: SettingsBuilder) -> SettingsBuilder {
+        builder
+            .mouse_speed(self.speed)
+            .mouse_delay(self.delay)
+            .mouse_acceleration_speed(self.acceleration_speed)
+            .mouse_acceleration_delay(self.acceleration_delay)
+            .mouse_wheel_speed(self.wheel_speed)
+            .mouse_wheel_delay(self.wheel_delay)
+            .mouse_speed_limit(self.speed_limit)
+    }
+}
+
+impl From<&Settings> for MouseConfig {
+    fn from(settings: &Settings) -> Self {
+        Self {
+            speed: settings.mouse_speed,
+            delay: settings.mouse_delay,
+            acceleration_speed: settings.mouse_acceleration_speed,
+            acceleration_delay: settings.mouse_acceleration_delay,
+            wheel_speed: settings.mouse_wheel_speed,
+            wheel_delay: settings.mouse_wheel_delay,
+            speed_limit: settings.mouse_speed_limit,
+        }
+    }
+}
+
+#[tracing::instrument(skip(focus))]
+pub async fn mouse_config_get(focus: &mut Focus) -> Result<MouseConfig> {
+    Ok(MouseConfig {
+        speed: focus.mouse_speed_get().await?,
+        delay: focus.mouse_delay_get().await?,
+        acceleration_speed: focus.mouse_acceleration_speed_get().await?,
+        acceleration_delay: focus.mouse_acceleration_delay_get().await?,
+        wheel_speed: focus.mouse_wheel_speed_get().await?,
+        wheel_delay: focus.mouse_wheel_delay_get().await?,
+        speed_limit: focus.mouse_speed_limit_get().await?,
+    })
+}
+
+/// Validates every field before writing any of them.
+#[tracing::instrument(skip(focus))]
+pub async fn mouse_config_set(focus: &mut Focus, config: &MouseConfig) -> Result<()> {
+    config.validate()?;
+
+    focus.mouse_speed_set(config.speed).await?;
+    focus.mouse_delay_set(config.delay).await?;
+    focus
+        .mouse_acceleration_speed_set(config.acceleration_speed)
+        .await?;
+    focus
+        .mouse_acceleration_delay_set(config.acceleration_delay)
+        .await?;
+    focus.mouse_wheel_speed_set(config.wheel_speed).await?;
+    focus.mouse_wheel_delay_set(config.wheel_delay).await?;
+    focus.mouse_speed_limit_set(config.speed_limit).await?;
+
+    Ok(())
+}
@@ -0,0 +1,176 @@
+use serde_json::{json, Value};
+
+fn integer(max: u64) -> Value {
+    json!({ "type": "integer", "minimum": 0, "maximum": max })
+}
+
+fn byte() -> Value {
+    integer(u8::MAX as u64)
+}
+
+fn boolean() -> Value {
+    json!({ "type": "boolean" })
+}
+
+/// `Duration` serializes as whole seconds plus the remaining nanoseconds.
+fn duration() -> Value {
+    json!({
+        "type": "object",
+        "properties": {
+            "secs": integer(u64::MAX),
+            "nanos": integer(999_999_999),
+        },
+        "required": ["secs", "nanos"],
+        "additionalProperties": false,
+    })
+}
+
+/// Fields the firmware may not have serialize as `null`.
+fn optional(schema: Value) -> Value {
+    json!({ "anyOf": [schema, { "type": "null" }] })
+}
+
+fn unit_enum(variants: &[&str]) -> Value {
+    json!({ "type": "string", "enum": variants })
+}
+
+fn array(items: Value) -> Value {
+    json!({ "type": "array", "items": items })
+}
+
+fn color(channels: &[&str]) -> Value {
+    let properties: serde_json::Map<String, Value> = channels
+        .iter()
+        .map(|channel| (channel.to_string(), byte()))
+        .collect();
+
+    json!({
+        "type": "object",
+        "properties": properties,
+        "required": channels,
+        "additionalProperties": false,
+    })
+}
+
+/// JSON Schema (draft 2020-12) of `dygma_focus::Settings` as serialized by serde.
+pub fn settings_schema() -> Value {
+    let properties = json!({
+        "keymap_custom": array(integer(u16::MAX as u64)),
+        "keymap_default": array(integer(u16::MAX as u64)),
+        "keymap_only_custom": boolean(),
+        "settings_default_layer": byte(),
+        "superkeys_map": array(integer(u16::MAX as u64)),
+        "superkeys_wait_for": duration(),
+        "superkeys_timeout": duration(),
+        "superkeys_repeat": duration(),
+        "superkeys_hold_start": duration(),
+        "superkeys_overlap": integer(80),
+        "led_mode": unit_enum(&[
+            "Layer",
+            "Rainbow",
+            "Cycle",
+            "Stalker",
+            "Red",
+            "Green",
+            "Blue",
+            "White",
+            "Off",
+        ]),
+        "led_brightness_top": byte(),
+        "led_brightness_underglow": optional(byte()),
+        "led_brightness_wireless_top": optional(byte()),
+        "led_brightness_wireless_underglow": optional(byte()),
+        "led_fade": optional(integer(u16::MAX as u64)),
+        "led_theme": array(color(&["r", "g", "b"])),
+        "palette_rgb": optional(array(color(&["r", "g", "b"]))),
+        "palette_rgbw": optional(array(color(&["r", "g", "b", "w"]))),
+        "color_map": array(byte()),
+        "led_idle_true_sleep": optional(boolean()),
+        "led_idle_true_sleep_time": optional(duration()),
+        "led_idle_time_limit": duration(),
+        "led_idle_wireless": optional(boolean()),
+        "qukeys_hold_timeout": duration(),
+        "qukeys_overlap_threshold": duration(),
+        "macros_map": array(byte()),
+        "mouse_speed": integer(127),
+        "mouse_delay": duration(),
+        "mouse_acceleration_speed": integer(127),
+        "mouse_acceleration_delay": duration(),
+        "mouse_wheel_speed": integer(127),
+        "mouse_wheel_delay": duration(),
+        "mouse_speed_limit": integer(127),
+        "wireless_battery_saving_mode": optional(boolean()),
+        "wireless_rf_power_level": optional(unit_enum(&["Low", "Medium", "High"])),
+        "wireless_rf_channel_hop": optional(boolean()),
+    });
+
+    let required = properties
+        .as_object()
+        .map(|properties| properties.keys().cloned().collect::<Vec<_>>())
+        .unwrap_or_default();
+
+    json!({
+        "$schema": "https://json-schema.org/draft/2020-12/schema",
+        "title": "Settings",
+        "type": "object",
+        "properties": properties,
+        "required": required,
+        "additionalProperties": false,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::settings::tests::sample_settings;
+    use dygma_focus::enums::LedMode;
+
+    fn type_of(value: &Value) -> &'static str {
+        match value {
+            Value::Null => "null",
+            Value::Bool(_) => "boolean",
+            Value::Number(_) => "integer",
+            Value::String(_) => "string",
+            Value::Array(_) => "array",
+            Value::Object(_) => "object",
+        }
+    }
+
+    fn allowed_types(schema: &Value) -> Vec<&str> {
+        match schema.get("anyOf") {
+            Some(Value::Array(options)) => options.iter().flat_map(allowed_types).collect(),
+            _ => schema["type"].as_str().into_iter().collect(),
+        }
+    }
+
+    #[test]
+    fn schema_matches_serialized_settings() {
+        let schema = settings_schema();
+        let properties = schema["properties"].as_object().unwrap();
+        let value = serde_json::to_value(sample_settings()).unwrap();
+        let fields = value.as_object().unwrap();
+
+        let mut schema_keys: Vec<_> = properties.keys().collect();
+        let mut field_keys: Vec<_> = fields.keys().collect();
+        schema_keys.sort();
+        field_keys.sort();
+        assert_eq!(schema_keys, field_keys);
+
+        for (name, field) in fields {
+            let allowed = allowed_types(&properties[name]);
+            assert!(
+                allowed.contains(&type_of(field)),
+                "{name} serialized as {} but the schema allows {allowed:?}",
+                type_of(field)
+            );
+        }
+    }
+
+    #[test]
+    fn schema_uses_the_firmware_led_modes() {
+        let schema = settings_schema();
+        let mode = serde_json::to_value(LedMode::Layer).unwrap();
+        let variants = schema["properties"]["led_mode"]["enum"].as_array().unwrap();
+        assert!(variants.contains(&mode));
+    }
+}
@@ -0,0 +1,1281 @@
+use crate::color::PALETTE_SIZE;
+use crate::command::command_response;
+use crate::features::{supported_commands, supports};
+use anyhow::{anyhow, bail, Context, Result};
+use dygma_focus::hardware::Product;
+use dygma_focus::prelude::*;
+use log::warn;
+use semver::{Version, VersionReq};
+use std::fmt::Display;
+use std::time::Duration;
+
+const PALETTE: &str = "palette";
+
+/// Longest idle time the firmware accepts for the idle LED timers.
+pub const MAX_IDLE_SECONDS: u64 = 65_000;
+
+/// Settings layout versions and the firmware releases known to write them.
+///
+/// The dygma_focus virtual devices report `settings.version` 1 on firmware v1.0.9 for every
+/// product. Layout changes bump `settings.version`, so the 1.x releases are taken to share it.
+const SETTINGS_COMPATIBILITY: &[(&str, &str)] = &[("1", ">=1.0.0, <2.0.0")];
+
+/// Whether firmware `firmware_version` uses the settings layout `settings_version`.
+///
+/// `None` when the layout isn't in the table or the firmware version can't be parsed.
+pub fn is_settings_compatible(settings_version: &str, firmware_version: &str) -> Option<bool> {
+    let (_, range) = SETTINGS_COMPATIBILITY
+        .iter()
+        .find(|(version, _)| *version == settings_version.trim())?;
+    let range = VersionReq::parse(range).expect("valid compatibility range");
+
+    firmware_core(firmware_version).map(|version| range.matches(&version))
+}
+
+/// `major.minor.patch` of a `version` response such as `v1.0.9beta 7622bb53 c9d9b7b-dirty`.
+fn firmware_core(firmware_version: &str) -> Option<Version> {
+    let version = firmware_version
+        .split_whitespace()
+        .next()?
+        .trim_start_matches('v');
+    let end = version
+        .find(|c: char| !c.is_ascii_digit() && c != '.')
+        .unwrap_or(version.len());
+
+    Version::parse(&version[..end]).ok()
+}
+
+/// Settings together with the layout version they were read with, as stored in a backup.
+#[derive(Debug, Clone)]
+pub struct SettingsBackup {
+    pub settings: Settings,
+    pub settings_version: String,
+}
+
+impl SettingsBackup {
+    pub async fn from_device(focus: &mut Focus) -> Result<Self> {
+        Ok(Self {
+            settings: focus.settings_get().await?,
+            settings_version: focus.settings_version_get().await?.trim().to_string(),
+        })
+    }
+
+    /// Whether the backup can be restored onto firmware `firmware_version`.
+    ///
+    /// Layouts missing from the compatibility table are never considered compatible.
+    pub fn is_compatible_with(&self, firmware_version: &str) -> bool {
+        is_settings_compatible(&self.settings_version, firmware_version) == Some(true)
+    }
+}
+
+/// Restores settings from a backup, refusing when the backup's settings layout doesn't match
+/// the device's firmware.
+///
+/// Layouts missing from the compatibility table are only restored onto a device reporting
+/// the same `settings.version`.
+#[tracing::instrument(skip(focus, backup))]
+pub async fn settings_restore(focus: &mut Focus, backup: &SettingsBackup) -> Result<()> {
+    let firmware_version = focus.version().await?;
+    match is_settings_compatible(&backup.settings_version, &firmware_version) {
+        Some(true) => {}
+        Some(false) => bail!(
+            "Settings version {} is not compatible with firmware {}",
+            backup.settings_version,
+            firmware_version.trim()
+        ),
+        None => {
+            let device_version = focus.settings_version_get().await?;
+            warn!(
+                "Settings version {} is not in the compatibility table, comparing with the device's {}",
+                backup.settings_version,
+                device_version.trim()
+            );
+            if backup.settings_version.trim() != device_version.trim() {
+                bail!(
+                    "Settings version {} is not compatible with the device's settings version {}",
+                    backup.settings_version,
+                    device_version.trim()
+                );
+            }
+        }
+    }
+
+    focus.settings_set(&backup.settings).await?;
+
+    Ok(())
+}
+
+/// A settings field that couldn't be read.
+#[derive(Debug)]
+pub struct SettingsReadError {
+    pub field: &'static str,
+    pub error: anyhow::Error,
+}
+
+/// Settings read field by field.
+///
+/// Fields that failed to read hold a zero value and are listed in `read_errors`.
+#[derive(Debug)]
+pub struct PartialSettings {
+    pub settings: Settings,
+    pub read_errors: Vec<SettingsReadError>,
+}
+
+impl PartialSettings {
+    pub fn is_complete(&self) -> bool {
+        self.read_errors.is_empty()
+    }
+
+    /// All-or-nothing view, failing with the first field that couldn't be read.
+    pub fn into_strict(self) -> Result<Settings> {
+        match self.read_errors.into_iter().next() {
+            None => Ok(self.settings),
+            Some(SettingsReadError { field, error }) => {
+                Err(error.context(format!("Failed to read {}", field)))
+            }
+        }
+    }
+}
+
+/// Reads the settings like `Focus::settings_get`, without aborting on the first failing field.
+///
+/// Optional fields are `None` when the firmware doesn't list their command in `help`. When it
+/// does, a failed read is recorded in `read_errors` like any other field. Use
+/// [`PartialSettings::into_strict`] for an all-or-nothing read.
+#[tracing::instrument(skip(focus))]
+pub async fn settings_get_partial(focus: &mut Focus) -> Result<PartialSettings> {
+    let commands = supported_commands(focus).await?;
+    let mut read_errors = Vec::new();
+
+    macro_rules! read {
+        ($field:literal, $getter:ident, $default:expr) => {
+            record(&mut read_errors, $field, focus.$getter().await).unwrap_or($default)
+        };
+        ($field:literal, $getter:ident if $command:literal) => {
+            if commands.contains($command) {
+                record(&mut read_errors, $field, focus.$getter().await)
+            } else {
+                None
+            }
+        };
+    }
+
+    let palette_components = if commands.contains(PALETTE) {
+        record(
+            &mut read_errors,
+            "palette",
+            command_response(focus, PALETTE).await,
+        )
+        .map(|palette| palette.split_whitespace().count())
+    } else {
+        None
+    };
+    let (palette_rgb, palette_rgbw) = match palette_components {
+        None => (None, None),
+        Some(n) if n == PALETTE_SIZE * 4 => (
+            None,
+            record(
+                &mut read_errors,
+                "palette_rgbw",
+                focus.palette_rgbw_get().await,
+            ),
+        ),
+        Some(n) if n == PALETTE_SIZE * 3 => (
+            record(
+                &mut read_errors,
+                "palette_rgb",
+                focus.palette_rgb_get().await,
+            ),
+            None,
+        ),
+        Some(n) => {
+            record::<()>(
+                &mut read_errors,
+                "palette",
+                Err(anyhow!(
+                    "Palette has {} components, expected RGB or RGBW",
+                    n
+                )),
+            );
+            (None, None)
+        }
+    };
+
+    let settings = Settings {
+        keymap_custom: read!("keymap_custom", keymap_custom_get, Vec::new()),
+        keymap_default: read!("keymap_default", keymap_default_get, Vec::new()),
+        keymap_only_custom: read!("keymap_only_custom", keymap_only_custom_get, false),
+        settings_default_layer: read!("settings_default_layer", settings_default_layer_get, 0),
+        superkeys_map: read!("superkeys_map", superkeys_map_get, Vec::new()),
+        superkeys_wait_for: read!("superkeys_wait_for", superkeys_wait_for_get, Duration::ZERO),
+        superkeys_timeout: read!("superkeys_timeout", superkeys_timeout_get, Duration::ZERO),
+        superkeys_repeat: read!("superkeys_repeat", superkeys_repeat_get, Duration::ZERO),
+        superkeys_hold_start: read!(
+            "superkeys_hold_start",
+            superkeys_hold_start_get,
+            Duration::ZERO
+        ),
+        superkeys_overlap: read!("superkeys_overlap", superkeys_overlap_get, 0),
+        led_mode: read!("led_mode", led_mode_get, LedMode::Layer),
+        led_brightness_top: read!("led_brightness_top", led_brightness_top_get, 0),
+        led_brightness_underglow: read!("led_brightness_underglow", led_brightness_underglow_get if "led.brightnessUG"),
+        led_brightness_wireless_top: read!("led_brightness_wireless_top", led_brightness_wireless_top_get if "led.brightness.wireless"),
+        led_brightness_wireless_underglow: read!("led_brightness_wireless_underglow", led_brightness_wireless_underglow_get if "led.brightnessUG.wireless"),
+        led_fade: read!("led_fade", led_fade_get if "led.fade"),
+        led_theme: read!("led_theme", led_theme_get, Vec::new()),
+        palette_rgb,
+        palette_rgbw,
+        color_map: read!("color_map", color_map_get, Vec::new()),
+        led_idle_true_sleep: read!("led_idle_true_sleep", led_idle_true_sleep_get if "idleleds.true_sleep"),
+        led_idle_true_sleep_time: read!("led_idle_true_sleep_time", led_idle_true_sleep_time_get if "idleleds.true_sleep_time"),
+        led_idle_time_limit: read!(
+            "led_idle_time_limit",
+            led_idle_time_limit_get,
+            Duration::ZERO
+        ),
+        led_idle_wireless: read!("led_idle_wireless", led_idle_wireless_get if "idleleds.wireless"),
+        qukeys_hold_timeout: read!(
+            "qukeys_hold_timeout",
+            qukeys_hold_timeout_get,
+            Duration::ZERO
+        ),
+        qukeys_overlap_threshold: read!(
+            "qukeys_overlap_threshold",
+            qukeys_overlap_threshold_get,
+            Duration::ZERO
+        ),
+        macros_map: read!("macros_map", macros_map_get, Vec::new()),
+        mouse_speed: read!("mouse_speed", mouse_speed_get, 0),
+        mouse_delay: read!("mouse_delay", mouse_delay_get, Duration::ZERO),
+        mouse_acceleration_speed: read!(
+            "mouse_acceleration_speed",
+            mouse_acceleration_speed_get,
+            0
+        ),
+        mouse_acceleration_delay: read!(
+            "mouse_acceleration_delay",
+            mouse_acceleration_delay_get,
+            Duration::ZERO
+        ),
+        mouse_wheel_speed: read!("mouse_wheel_speed", mouse_wheel_speed_get, 0),
+        mouse_wheel_delay: read!("mouse_wheel_delay", mouse_wheel_delay_get, Duration::ZERO),
+        mouse_speed_limit: read!("mouse_speed_limit", mouse_speed_limit_get, 0),
+        wireless_battery_saving_mode: read!("wireless_battery_saving_mode", wireless_battery_saving_mode_get if "wireless.battery.savingMode"),
+        wireless_rf_power_level: read!("wireless_rf_power_level", wireless_rf_power_level_get if "wireless.rf.power"),
+        wireless_rf_channel_hop: read!("wireless_rf_channel_hop", wireless_rf_channel_hop_get if "wireless.rf.channelHop"),
+    };
+
+    Ok(PartialSettings {
+        settings,
+        read_errors,
+    })
+}
+
+/// Keeps a successfully read value, or records why it couldn't be read.
+fn record<T>(
+    read_errors: &mut Vec<SettingsReadError>,
+    field: &'static str,
+    result: Result<T>,
+) -> Option<T> {
+    match result {
+        Ok(value) => Some(value),
+        Err(error) => {
+            warn!("Failed to read {}: {}", field, error);
+            read_errors.push(SettingsReadError { field, error });
+            None
+        }
+    }
+}
+
+/// CRC of the settings stored in EEPROM.
+pub async fn settings_crc(focus: &mut Focus) -> Result<String> {
+    Ok(focus.settings_crc().await?.trim().to_string())
+}
+
+/// Whether the settings in EEPROM match their stored CRC.
+///
+/// RAM-only state such as `layer.activate` and `layer.moveTo` is never persisted
+/// and isn't covered by the CRC.
+#[tracing::instrument(skip(focus))]
+pub async fn settings_persisted(focus: &mut Focus) -> Result<bool> {
+    let crc = settings_crc(focus).await?;
+
+    Ok(!crc.is_empty() && focus.settings_valid().await?)
+}
+
+/// Writes the settings and confirms they reached EEPROM by comparing the CRC before and after.
+///
+/// Returns `false` when the CRC didn't change, meaning either the settings were already
+/// identical or the write didn't persist.
+#[tracing::instrument(skip(focus, settings))]
+pub async fn settings_set_verified(focus: &mut Focus, settings: &Settings) -> Result<bool> {
+    let before = settings_crc(focus).await?;
+
+    focus.settings_set(settings).await?;
+
+    let after = settings_crc(focus).await?;
+    if !settings_persisted(focus).await? {
+        bail!("Settings CRC {} does not match the stored settings", after);
+    }
+
+    if before == after {
+        warn!("Settings CRC unchanged after writing: {}", after);
+    }
+
+    Ok(before != after)
+}
+
+/// The firmware reported the settings as invalid after writing them.
+#[derive(Debug)]
+pub struct InvalidSettings {
+    /// Fields that read back differently than written, the likely cause.
+    pub fields: Vec<&'static str>,
+}
+
+impl Display for InvalidSettings {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if self.fields.is_empty() {
+            write!(f, "Settings are invalid, every field read back as written")
+        } else {
+            write!(f, "Settings are invalid, check: {}", self.fields.join(", "))
+        }
+    }
+}
+
+impl std::error::Error for InvalidSettings {}
+
+/// Writes every field, then checks `settings.valid?` once.
+///
+/// When the firmware reports the settings as invalid they are read back and compared
+/// with what was written, returning [`InvalidSettings`] with the fields that differ.
+#[tracing::instrument(skip(focus, settings))]
+pub async fn settings_set_and_validate(focus: &mut Focus, settings: &Settings) -> Result<()> {
+    focus.settings_set(settings).await?;
+
+    if focus.settings_valid().await? {
+        return Ok(());
+    }
+
+    let read_back = focus.settings_get().await?;
+    let fields = differing_fields(settings, &read_back);
+    warn!(
+        "Settings invalid after writing, differing fields: {:?}",
+        fields
+    );
+
+    Err(InvalidSettings { fields }.into())
+}
+
+/// A field written by [`settings_set_resumable`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SettingsWriteField {
+    KeymapOnlyCustom,
+    SettingsDefaultLayer,
+    LedMode,
+    LedBrightnessTop,
+    LedBrightnessUnderglow,
+    LedBrightnessWirelessTop,
+    LedBrightnessWirelessUnderglow,
+    LedFade,
+    LedIdleTimeLimit,
+    LedIdleTrueSleep,
+    LedIdleTrueSleepTime,
+    QukeysHoldTimeout,
+    QukeysOverlapThreshold,
+    SuperkeysWaitFor,
+    SuperkeysTimeout,
+    SuperkeysRepeat,
+    SuperkeysHoldStart,
+    SuperkeysOverlap,
+    MouseSpeed,
+    MouseDelay,
+    MouseAccelerationSpeed,
+    MouseAccelerationDelay,
+    MouseWheelSpeed,
+    MouseWheelDelay,
+    MouseSpeedLimit,
+    WirelessBatterySavingMode,
+    WirelessRfPowerLevel,
+    WirelessRfChannelHop,
+}
+
+impl SettingsWriteField {
+    /// Name of the field in [`Settings`].
+    pub fn name(&self) -> &'static str {
+        match self {
+            SettingsWriteField::KeymapOnlyCustom => "keymap_only_custom",
+            SettingsWriteField::SettingsDefaultLayer => "settings_default_layer",
+            SettingsWriteField::LedMode => "led_mode",
+            SettingsWriteField::LedBrightnessTop => "led_brightness_top",
+            SettingsWriteField::LedBrightnessUnderglow => "led_brightness_underglow",
+            SettingsWriteField::LedBrightnessWirelessTop => "led_brightness_wireless_top",
+            SettingsWriteField::LedBrightnessWirelessUnderglow => {
+                "led_brightness_wireless_underglow"
+            }
+            SettingsWriteField::LedFade => "led_fade",
+            SettingsWriteField::LedIdleTimeLimit => "led_idle_time_limit",
+            SettingsWriteField::LedIdleTrueSleep => "led_idle_true_sleep",
+            SettingsWriteField::LedIdleTrueSleepTime => "led_idle_true_sleep_time",
+            SettingsWriteField::QukeysHoldTimeout => "qukeys_hold_timeout",
+            SettingsWriteField::QukeysOverlapThreshold => "qukeys_overlap_threshold",
+            SettingsWriteField::SuperkeysWaitFor => "superkeys_wait_for",
+            SettingsWriteField::SuperkeysTimeout => "superkeys_timeout",
+            SettingsWriteField::SuperkeysRepeat => "superkeys_repeat",
+            SettingsWriteField::SuperkeysHoldStart => "superkeys_hold_start",
+            SettingsWriteField::SuperkeysOverlap => "superkeys_overlap",
+            SettingsWriteField::MouseSpeed => "mouse_speed",
+            SettingsWriteField::MouseDelay => "mouse_delay",
+            SettingsWriteField::MouseAccelerationSpeed => "mouse_acceleration_speed",
+            SettingsWriteField::MouseAccelerationDelay => "mouse_acceleration_delay",
+            SettingsWriteField::MouseWheelSpeed => "mouse_wheel_speed",
+            SettingsWriteField::MouseWheelDelay => "mouse_wheel_delay",
+            SettingsWriteField::MouseSpeedLimit => "mouse_speed_limit",
+            SettingsWriteField::WirelessBatterySavingMode => "wireless_battery_saving_mode",
+            SettingsWriteField::WirelessRfPowerLevel => "wireless_rf_power_level",
+            SettingsWriteField::WirelessRfChannelHop => "wireless_rf_channel_hop",
+        }
+    }
+}
+
+impl Display for SettingsWriteField {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.name())
+    }
+}
+
+/// Order in which [`settings_set_resumable`] writes the fields.
+pub const SETTINGS_FIELDS: &[SettingsWriteField] = &[
+    SettingsWriteField::KeymapOnlyCustom,
+    SettingsWriteField::SettingsDefaultLayer,
+    SettingsWriteField::LedMode,
+    SettingsWriteField::LedBrightnessTop,
+    SettingsWriteField::LedBrightnessUnderglow,
+    SettingsWriteField::LedBrightnessWirelessTop,
+    SettingsWriteField::LedBrightnessWirelessUnderglow,
+    SettingsWriteField::LedFade,
+    SettingsWriteField::LedIdleTimeLimit,
+    SettingsWriteField::LedIdleTrueSleep,
+    SettingsWriteField::LedIdleTrueSleepTime,
+    SettingsWriteField::QukeysHoldTimeout,
+    SettingsWriteField::QukeysOverlapThreshold,
+    SettingsWriteField::SuperkeysWaitFor,
+    SettingsWriteField::SuperkeysTimeout,
+    SettingsWriteField::SuperkeysRepeat,
+    SettingsWriteField::SuperkeysHoldStart,
+    SettingsWriteField::SuperkeysOverlap,
+    SettingsWriteField::MouseSpeed,
+    SettingsWriteField::MouseDelay,
+    SettingsWriteField::MouseAccelerationSpeed,
+    SettingsWriteField::MouseAccelerationDelay,
+    SettingsWriteField::MouseWheelSpeed,
+    SettingsWriteField::MouseWheelDelay,
+    SettingsWriteField::MouseSpeedLimit,
+    SettingsWriteField::WirelessBatterySavingMode,
+    SettingsWriteField::WirelessRfPowerLevel,
+    SettingsWriteField::WirelessRfChannelHop,
+];
+
+/// A settings write stopped partway, pass `resume_from` back to continue where it failed.
+#[derive(Debug)]
+pub struct SettingsSetInterrupted {
+    /// Index into [`SETTINGS_FIELDS`] of the field that failed, every earlier field was written.
+    pub resume_from: usize,
+    pub field: SettingsWriteField,
+    pub error: String,
+}
+
+impl Display for SettingsSetInterrupted {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "Settings write interrupted at {} ({}/{}): {}",
+            self.field,
+            self.resume_from,
+            SETTINGS_FIELDS.len(),
+            self.error
+        )
+    }
+}
+
+impl std::error::Error for SettingsSetInterrupted {}
+
+async fn write_setting(
+    focus: &mut Focus,
+    settings: &Settings,
+    field: SettingsWriteField,
+) -> Result<()> {
+    match field {
+        SettingsWriteField::KeymapOnlyCustom => {
+            focus
+                .keymap_only_custom_set(settings.keymap_only_custom)
+                .await?
+        }
+        SettingsWriteField::SettingsDefaultLayer => {
+            focus
+                .settings_default_layer_set(settings.settings_default_layer)
+                .await?
+        }
+        SettingsWriteField::LedMode => focus.led_mode_set(settings.led_mode).await?,
+        SettingsWriteField::LedBrightnessTop => {
+            focus
+                .led_brightness_top_set(settings.led_brightness_top)
+                .await?
+        }
+        SettingsWriteField::LedBrightnessUnderglow => {
+            if let Some(value) = settings.led_brightness_underglow {
+                focus.led_brightness_underglow_set(value).await?;
+            }
+        }
+        SettingsWriteField::LedBrightnessWirelessTop => {
+            if let Some(value) = settings.led_brightness_wireless_top {
+                focus.led_brightness_wireless_top_set(value).await?;
+            }
+        }
+        SettingsWriteField::LedBrightnessWirelessUnderglow => {
+            if let Some(value) = settings.led_brightness_wireless_underglow {
+                focus.led_brightness_wireless_underglow_set(value).await?;
+            }
+        }
+        SettingsWriteField::LedFade => {
+            if let Some(value) = settings.led_fade {
+                focus.led_fade_set(value).await?;
+            }
+        }
+        SettingsWriteField::LedIdleTimeLimit => {
+            focus
+                .led_idle_time_limit_set(settings.led_idle_time_limit)
+                .await?
+        }
+        SettingsWriteField::LedIdleTrueSleep => {
+            if let Some(value) = settings.led_idle_true_sleep {
+                focus.led_idle_true_sleep_set(value).await?;
+            }
+        }
+        SettingsWriteField::LedIdleTrueSleepTime => {
+            if let Some(value) = settings.led_idle_true_sleep_time {
+                focus.led_idle_true_sleep_time_set(value).await?;
+            }
+        }
+        SettingsWriteField::QukeysHoldTimeout => {
+            focus
+                .qukeys_hold_timeout_set(settings.qukeys_hold_timeout)
+                .await?
+        }
+        SettingsWriteField::QukeysOverlapThreshold => {
+            focus
+                .qukeys_overlap_threshold_set(settings.qukeys_overlap_threshold)
+                .await?
+        }
+        SettingsWriteField::SuperkeysWaitFor => {
+            focus
+                .superkeys_wait_for_set(settings.superkeys_wait_for)
+                .await?
+        }
+        SettingsWriteField::SuperkeysTimeout => {
+            focus
+                .superkeys_timeout_set(settings.superkeys_timeout)
+                .await?
+        }
+        SettingsWriteField::SuperkeysRepeat => {
+            focus
+                .superkeys_repeat_set(settings.superkeys_repeat)
+                .await?
+        }
+        SettingsWriteField::SuperkeysHoldStart => {
+            focus
+                .superkeys_hold_start_set(settings.superkeys_hold_start)
+                .await?
+        }
+        SettingsWriteField::SuperkeysOverlap => {
+            focus
+                .superkeys_overlap_set(settings.superkeys_overlap)
+                .await?
+        }
+        SettingsWriteField::MouseSpeed => focus.mouse_speed_set(settings.mouse_speed).await?,
+        SettingsWriteField::MouseDelay => focus.mouse_delay_set(settings.mouse_delay).await?,
+        SettingsWriteField::MouseAccelerationSpeed => {
+            focus
+                .mouse_acceleration_speed_set(settings.mouse_acceleration_speed)
+                .await?
+        }
+        SettingsWriteField::MouseAccelerationDelay => {
+            focus
+                .mouse_acceleration_delay_set(settings.mouse_acceleration_delay)
+                .await?
+        }
+        SettingsWriteField::MouseWheelSpeed => {
+            focus
+                .mouse_wheel_speed_set(settings.mouse_wheel_speed)
+                .await?
+        }
+        SettingsWriteField::MouseWheelDelay => {
+            focus
+                .mouse_wheel_delay_set(settings.mouse_wheel_delay)
+                .await?
+        }
+        SettingsWriteField::MouseSpeedLimit => {
+            focus
+                .mouse_speed_limit_set(settings.mouse_speed_limit)
+                .await?
+        }
+        SettingsWriteField::WirelessBatterySavingMode => {
+            if let Some(value) = settings.wireless_battery_saving_mode {
+                focus.wireless_battery_saving_mode_set(value).await?;
+            }
+        }
+        SettingsWriteField::WirelessRfPowerLevel => {
+            if let Some(value) = &settings.wireless_rf_power_level {
+                focus.wireless_rf_power_level_set(*value).await?;
+            }
+        }
+        SettingsWriteField::WirelessRfChannelHop => {
+            if let Some(value) = settings.wireless_rf_channel_hop {
+                focus.wireless_rf_channel_hop_set(value).await?;
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Writes the settings field by field, starting at `resume_from`.
+///
+/// Pass `0` for a full write. When a field fails, [`SettingsSetInterrupted`] records where
+/// to resume so a retry on a flaky connection doesn't redo the fields already written.
+#[tracing::instrument(skip(focus, settings))]
+pub async fn settings_set_resumable(
+    focus: &mut Focus,
+    settings: &Settings,
+    resume_from: usize,
+) -> Result<()> {
+    for (index, &field) in SETTINGS_FIELDS.iter().enumerate().skip(resume_from) {
+        if let Err(e) = write_setting(focus, settings, field).await {
+            warn!("Failed to write {}: {}", field, e);
+            return Err(SettingsSetInterrupted {
+                resume_from: index,
+                field,
+                error: e.to_string(),
+            }
+            .into());
+        }
+    }
+
+    Ok(())
+}
+
+const KEYSCAN: &str = "hardware.keyscan";
+
+/// Reads `hardware.keyscan`, the interval between key scans, as the raw number the firmware
+/// reports.
+///
+/// The firmware documents neither the unit nor a version where it changed, so the value is
+/// not converted to a `Duration`. Returns `None` when the command isn't listed.
+#[tracing::instrument(skip(focus))]
+pub async fn keyscan_interval_get(focus: &mut Focus) -> Result<Option<u32>> {
+    if !supports(focus, KEYSCAN).await? {
+        return Ok(None);
+    }
+
+    let response = command_response(focus, KEYSCAN).await?;
+    let raw = response
+        .trim()
+        .parse()
+        .with_context(|| format!("Invalid {} response: {}", KEYSCAN, response))?;
+
+    Ok(Some(raw))
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct IdleTrueSleep {
+    pub enabled: bool,
+    pub time: Duration,
+}
+
+pub fn validate_idle_time(time: Duration) -> Result<()> {
+    if time.as_secs() > MAX_IDLE_SECONDS {
+        bail!(
+            "Idle time of {}s exceeds the maximum of {}s",
+            time.as_secs(),
+            MAX_IDLE_SECONDS
+        );
+    }
+
+    Ok(())
+}
+
+/// Reads the true sleep settings.
+///
+/// Returns `None` when the firmware doesn't list `idleleds.true_sleep`, IO and parse errors are returned.
+#[tracing::instrument(skip(focus))]
+pub async fn idle_true_sleep_get(focus: &mut Focus) -> Result<Option<IdleTrueSleep>> {
+    if !supports(focus, "idleleds.true_sleep").await? {
+        return Ok(None);
+    }
+
+    Ok(Some(IdleTrueSleep {
+        enabled: focus.led_idle_true_sleep_get().await?,
+        time: focus.led_idle_true_sleep_time_get().await?,
+    }))
+}
+
+#[tracing::instrument(skip(focus))]
+pub async fn idle_true_sleep_set(focus: &mut Focus, value: &IdleTrueSleep) -> Result<()> {
+    validate_idle_time(value.time)?;
+
+    if !supports(focus, "idleleds.true_sleep").await? {
+        bail!("Firmware does not support idleleds.true_sleep");
+    }
+
+    focus.led_idle_true_sleep_set(value.enabled).await?;
+    focus.led_idle_true_sleep_time_set(value.time).await?;
+
+    Ok(())
+}
+
+macro_rules! setters {
+    ($($field:ident: $ty:ty),* $(,)?) => {
+        $(
+            pub fn $field(mut self, value: $ty) -> Self {
+                self.settings.$field = value;
+                self
+            }
+        )*
+    };
+}
+
+/// Names of the fields that differ between two settings.
+pub fn differing_fields(a: &Settings, b: &Settings) -> Vec<&'static str> {
+    macro_rules! compare {
+        ($($field:ident),* $(,)?) => {{
+            // Destructured without `..` so a new `Settings` field fails to compile here.
+            let Settings { $($field: _),* } = a;
+
+            let mut fields = Vec::new();
+            $(
+                if a.$field != b.$field {
+                    fields.push(stringify!($field));
+                }
+            )*
+            fields
+        }};
+    }
+
+    compare!(
+        keymap_custom,
+        keymap_default,
+        keymap_only_custom,
+        settings_default_layer,
+        superkeys_map,
+        superkeys_wait_for,
+        superkeys_timeout,
+        superkeys_repeat,
+        superkeys_hold_start,
+        superkeys_overlap,
+        led_mode,
+        led_brightness_top,
+        led_brightness_underglow,
+        led_brightness_wireless_top,
+        led_brightness_wireless_underglow,
+        led_fade,
+        led_theme,
+        palette_rgb,
+        palette_rgbw,
+        color_map,
+        led_idle_true_sleep,
+        led_idle_true_sleep_time,
+        led_idle_time_limit,
+        led_idle_wireless,
+        qukeys_hold_timeout,
+        qukeys_overlap_threshold,
+        macros_map,
+        mouse_speed,
+        mouse_delay,
+        mouse_acceleration_speed,
+        mouse_acceleration_delay,
+        mouse_wheel_speed,
+        mouse_wheel_delay,
+        mouse_speed_limit,
+        wireless_battery_saving_mode,
+        wireless_rf_power_level,
+        wireless_rf_channel_hop,
+    )
+}
+
+/// Builds [`Settings`] from a baseline, overriding only the fields that are set.
+///
+/// ```no_run
+/// # async fn example() -> anyhow::Result<()> {
+/// use dygma_api::settings::SettingsBuilder;
+/// use dygma_focus::prelude::*;
+///
+/// let mut focus = Focus::new_first_available()?;
+/// let settings = SettingsBuilder::from_device(&mut focus)
+///     .await?
+///     .led_mode(LedMode::Layer)
+///     .led_brightness_top(200)
+///     .build();
+/// focus.settings_set(&settings).await?;
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug, Clone)]
+pub struct SettingsBuilder {
+    settings: Settings,
+}
+
+impl SettingsBuilder {
+    pub fn new(baseline: Settings) -> Self {
+        Self { settings: baseline }
+    }
+
+    /// Starts from the settings currently on the device.
+    pub async fn from_device(focus: &mut Focus) -> Result<Self> {
+        Ok(Self::new(focus.settings_get().await?))
+    }
+
+    setters! {
+        keymap_only_custom: bool,
+        settings_default_layer: u8,
+        led_mode: LedMode,
+        led_brightness_top: u8,
+        led_brightness_underglow: Option<u8>,
+        led_brightness_wireless_top: Option<u8>,
+        led_brightness_wireless_underglow: Option<u8>,
+        led_fade: Option<u16>,
+        led_idle_time_limit: Duration,
+        led_idle_true_sleep: Option<bool>,
+        led_idle_true_sleep_time: Option<Duration>,
+        qukeys_hold_timeout: Duration,
+        qukeys_overlap_threshold: Duration,
+        superkeys_wait_for: Duration,
+        superkeys_timeout: Duration,
+        superkeys_repeat: Duration,
+        superkeys_hold_start: Duration,
+        superkeys_overlap: u8,
+        mouse_speed: u8,
+        mouse_delay: Duration,
+        mouse_acceleration_speed: u8,
+        mouse_acceleration_delay: Duration,
+        mouse_wheel_speed: u8,
+        mouse_wheel_delay: Duration,
+        mouse_speed_limit: u8,
+        wireless_battery_saving_mode: Option<bool>,
+        wireless_rf_power_level: Option<WirelessPowerMode>,
+        wireless_rf_channel_hop: Option<bool>,
+    }
+
+    pub fn build(self) -> Settings {
+        self.settings
+    }
+}
+
+/// A single settings override, as stored in a [`SettingsPreset`].
+#[derive(Debug, Clone)]
+pub enum SettingValue {
+    LedBrightnessTop(u8),
+    LedBrightnessUnderglow(u8),
+    LedIdleTimeLimit(Duration),
+    WirelessBatterySavingMode(bool),
+    WirelessRfPowerLevel(WirelessPowerMode),
+    WirelessRfChannelHop(bool),
+}
+
+impl SettingValue {
+    fn apply(&self, builder: SettingsBuilder) -> SettingsBuilder {
+        match self {
+            SettingValue::LedBrightnessTop(value) => builder.led_brightness_top(*value),
+            SettingValue::LedBrightnessUnderglow(value) => {
+                builder.led_brightness_underglow(Some(*value))
+            }
+            SettingValue::LedIdleTimeLimit(value) => builder.led_idle_time_limit(*value),
+            SettingValue::WirelessBatterySavingMode(value) => {
+                builder.wireless_battery_saving_mode(Some(*value))
+            }
+            SettingValue::WirelessRfPowerLevel(value) => {
+                builder.wireless_rf_power_level(Some(*value))
+            }
+            SettingValue::WirelessRfChannelHop(value) => {
+                builder.wireless_rf_channel_hop(Some(*value))
+            }
+        }
+    }
+}
+
+/// Named set of overrides, only the listed fields are changed when applied.
+#[derive(Debug, Clone)]
+pub struct SettingsPreset {
+    pub name: String,
+    pub values: Vec<SettingValue>,
+}
+
+impl SettingsPreset {
+    pub fn new(name: &str, values: Vec<SettingValue>) -> Self {
+        Self {
+            name: name.to_string(),
+            values,
+        }
+    }
+
+    /// Short idle timeout and full RF power.
+    pub fn gaming() -> Self {
+        Self::new(
+            "Gaming",
+            vec![
+                SettingValue::LedIdleTimeLimit(Duration::from_secs(60)),
+                SettingValue::WirelessRfPowerLevel(WirelessPowerMode::High),
+                SettingValue::WirelessBatterySavingMode(false),
+            ],
+        )
+    }
+
+    /// Dim LEDs, quick idle and battery saving mode.
+    pub fn battery() -> Self {
+        Self::new(
+            "Battery",
+            vec![
+                SettingValue::LedBrightnessTop(50),
+                SettingValue::LedBrightnessUnderglow(0),
+                SettingValue::LedIdleTimeLimit(Duration::from_secs(30)),
+                SettingValue::WirelessBatterySavingMode(true),
+            ],
+        )
+    }
+
+    pub fn built_in() -> Vec<Self> {
+        vec![Self::gaming(), Self::battery()]
+    }
+
+    pub fn apply_to(&self, builder: SettingsBuilder) -> SettingsBuilder {
+        self.values
+            .iter()
+            .fold(builder, |builder, value| value.apply(builder))
+    }
+}
+
+/// Applies a preset over the settings currently on the device.
+#[tracing::instrument(skip(focus, preset), fields(preset = %preset.name))]
+pub async fn apply_preset(focus: &mut Focus, preset: &SettingsPreset) -> Result<()> {
+    let settings = preset
+        .apply_to(SettingsBuilder::from_device(focus).await?)
+        .build();
+    focus.settings_set(&settings).await?;
+
+    Ok(())
+}
+
+/// Firmware defaults for the settings that can be reset individually.
+///
+/// The values a factory-fresh device reports, as captured by the dygma_focus virtual devices
+/// in `hardware/types/hardware_virtual.rs`. They are the same on every product, apart from
+/// the LED brightness and idle time.
+pub mod defaults {
+    use dygma_focus::hardware::Product;
+    use std::time::Duration;
+
+    /// `led.brightness` and `led.brightnessUG`.
+    pub fn led_brightness(product: Product) -> u8 {
+        match product {
+            Product::Raise => 255,
+            Product::Defy => 153,
+        }
+    }
+
+    /// `idleleds.time_limit`.
+    pub fn led_idle_time_limit(product: Product) -> Duration {
+        match product {
+            Product::Raise => Duration::from_secs(600),
+            Product::Defy => Duration::from_secs(300),
+        }
+    }
+
+    /// `qukeys.holdTimeout`.
+    pub const QUKEYS_HOLD_TIMEOUT: Duration = Duration::from_millis(250);
+    /// `qukeys.overlapThreshold`.
+    pub const QUKEYS_OVERLAP_THRESHOLD: Duration = Duration::from_millis(80);
+    /// `superkeys.waitfor`.
+    pub const SUPERKEYS_WAIT_FOR: Duration = Duration::from_millis(500);
+    /// `superkeys.timeout`.
+    pub const SUPERKEYS_TIMEOUT: Duration = Duration::from_millis(250);
+    /// `superkeys.repeat`.
+    pub const SUPERKEYS_REPEAT: Duration = Duration::from_millis(20);
+    /// `superkeys.holdstart`.
+    pub const SUPERKEYS_HOLD_START: Duration = Duration::from_millis(200);
+    /// `superkeys.overlap`.
+    pub const SUPERKEYS_OVERLAP: u8 = 20;
+    /// `mouse.speed`.
+    pub const MOUSE_SPEED: u8 = 20;
+    /// `mouse.speedDelay`.
+    pub const MOUSE_DELAY: Duration = Duration::from_millis(2);
+    /// `mouse.accelSpeed`.
+    pub const MOUSE_ACCELERATION_SPEED: u8 = 20;
+    /// `mouse.accelDelay`.
+    pub const MOUSE_ACCELERATION_DELAY: Duration = Duration::from_millis(2);
+    /// `mouse.wheelSpeed`.
+    pub const MOUSE_WHEEL_SPEED: u8 = 2;
+    /// `mouse.wheelDelay`.
+    pub const MOUSE_WHEEL_DELAY: Duration = Duration::from_millis(100);
+    /// `mouse.speedLimit`.
+    pub const MOUSE_SPEED_LIMIT: u8 = 127;
+}
+
+/// A setting that can be reset to its default on its own.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SettingsField {
+    LedBrightnessTop,
+    LedBrightnessUnderglow,
+    LedIdleTimeLimit,
+    QukeysHoldTimeout,
+    QukeysOverlapThreshold,
+    SuperkeysWaitFor,
+    SuperkeysTimeout,
+    SuperkeysRepeat,
+    SuperkeysHoldStart,
+    SuperkeysOverlap,
+    MouseSpeed,
+    MouseDelay,
+    MouseAccelerationSpeed,
+    MouseAccelerationDelay,
+    MouseWheelSpeed,
+    MouseWheelDelay,
+    MouseSpeedLimit,
+}
+
+impl SettingsField {
+    fn reset(&self, builder: SettingsBuilder, product: Product) -> SettingsBuilder {
+        match self {
+            SettingsField::LedBrightnessTop => {
+                builder.led_brightness_top(defaults::led_brightness(product))
+            }
+            SettingsField::LedBrightnessUnderglow => {
+                builder.led_brightness_underglow(Some(defaults::led_brightness(product)))
+            }
+            SettingsField::LedIdleTimeLimit => {
+                builder.led_idle_time_limit(defaults::led_idle_time_limit(product))
+            }
+            SettingsField::QukeysHoldTimeout => {
+                builder.qukeys_hold_timeout(defaults::QUKEYS_HOLD_TIMEOUT)
+            }
+            SettingsField::QukeysOverlapThreshold => {
+                builder.qukeys_overlap_threshold(defaults::QUKEYS_OVERLAP_THRESHOLD)
+            }
+            SettingsField::SuperkeysWaitFor => {
+                builder.superkeys_wait_for(defaults::SUPERKEYS_WAIT_FOR)
+            }
+            SettingsField::SuperkeysTimeout => {
+                builder.superkeys_timeout(defaults::SUPERKEYS_TIMEOUT)
+            }
+            SettingsField::SuperkeysRepeat => builder.superkeys_repeat(defaults::SUPERKEYS_REPEAT),
+            SettingsField::SuperkeysHoldStart => {
+                builder.superkeys_hold_start(defaults::SUPERKEYS_HOLD_START)
+            }
+            SettingsField::SuperkeysOverlap => {
+                builder.superkeys_overlap(defaults::SUPERKEYS_OVERLAP)
+            }
+            SettingsField::MouseSpeed => builder.mouse_speed(defaults::MOUSE_SPEED),
+            SettingsField::MouseDelay => builder.mouse_delay(defaults::MOUSE_DELAY),
+            SettingsField::MouseAccelerationSpeed => {
+                builder.mouse_acceleration_speed(defaults::MOUSE_ACCELERATION_SPEED)
+            }
+            SettingsField::MouseAccelerationDelay => {
+                builder.mouse_acceleration_delay(defaults::MOUSE_ACCELERATION_DELAY)
+            }
+            SettingsField::MouseWheelSpeed => {
+                builder.mouse_wheel_speed(defaults::MOUSE_WHEEL_SPEED)
+            }
+            SettingsField::MouseWheelDelay => {
+                builder.mouse_wheel_delay(defaults::MOUSE_WHEEL_DELAY)
+            }
+            SettingsField::MouseSpeedLimit => {
+                builder.mouse_speed_limit(defaults::MOUSE_SPEED_LIMIT)
+            }
+        }
+    }
+}
+
+/// Resets a single setting to its firmware default, leaving the others untouched.
+///
+/// Some defaults differ between products, so the product of the connected device is needed.
+#[tracing::instrument(skip(focus))]
+pub async fn reset_field(focus: &mut Focus, product: Product, field: SettingsField) -> Result<()> {
+    let settings = field
+        .reset(SettingsBuilder::from_device(focus).await?, product)
+        .build();
+    focus.settings_set(&settings).await?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+
+    pub(crate) fn sample_settings() -> Settings {
+        Settings {
+            keymap_custom: vec![41, 30],
+            keymap_default: vec![41, 30],
+            keymap_only_custom: false,
+            settings_default_layer: 0,
+            superkeys_map: vec![0, 0],
+            superkeys_wait_for: Duration::from_millis(500),
+            superkeys_timeout: Duration::from_millis(250),
+            superkeys_repeat: Duration::from_millis(20),
+            superkeys_hold_start: Duration::from_millis(200),
+            superkeys_overlap: 20,
+            led_mode: LedMode::Layer,
+            led_brightness_top: 255,
+            led_brightness_underglow: Some(255),
+            led_brightness_wireless_top: Some(128),
+            led_brightness_wireless_underglow: Some(128),
+            led_fade: Some(0),
+            led_theme: vec![RGB { r: 255, g: 0, b: 0 }],
+            palette_rgb: Some(vec![RGB { r: 0, g: 255, b: 0 }]),
+            palette_rgbw: Some(vec![RGBW {
+                r: 0,
+                g: 0,
+                b: 255,
+                w: 0,
+            }]),
+            color_map: vec![0, 1],
+            led_idle_true_sleep: Some(true),
+            led_idle_true_sleep_time: Some(Duration::from_secs(600)),
+            led_idle_time_limit: Duration::from_secs(300),
+            led_idle_wireless: Some(false),
+            qukeys_hold_timeout: Duration::from_millis(250),
+            qukeys_overlap_threshold: Duration::from_millis(80),
+            macros_map: vec![0, 0],
+            mouse_speed: 1,
+            mouse_delay: Duration::from_millis(10),
+            mouse_acceleration_speed: 1,
+            mouse_acceleration_delay: Duration::from_millis(50),
+            mouse_wheel_speed: 1,
+            mouse_wheel_delay: Duration::from_millis(100),
+            mouse_speed_limit: 127,
+            wireless_battery_saving_mode: Some(true),
+            wireless_rf_power_level: Some(WirelessPowerMode::Medium),
+            wireless_rf_channel_hop: Some(true),
+        }
+    }
+
+    #[test]
+    fn known_layout_is_compatible_with_its_firmware() {
+        assert_eq!(
+            is_settings_compatible("1", "v1.0.9beta 7622bb53 c9d9b7b-dirty"),
+            Some(true)
+        );
+
+        let backup = SettingsBackup {
+            settings: sample_settings(),
+            settings_version: "1".to_string(),
+        };
+        assert!(backup.is_compatible_with("v1.2.0"));
+    }
+
+    #[test]
+    fn known_layout_is_incompatible_with_other_firmware() {
+        assert_eq!(is_settings_compatible("1", "v2.0.0"), Some(false));
+        assert_eq!(is_settings_compatible("1", "v0.9.0"), Some(false));
+    }
+
+    #[test]
+    fn unknown_layout_or_firmware_is_not_decided() {
+        assert_eq!(is_settings_compatible("7", "v1.0.0"), None);
+        assert_eq!(is_settings_compatible("1", "garbage"), None);
+
+        let backup = SettingsBackup {
+            settings: sample_settings(),
+            settings_version: "7".to_string(),
+        };
+        assert!(!backup.is_compatible_with("v1.0.0"));
+    }
+
+    #[test]
+    fn reset_changes_only_the_targeted_field() {
+        let fields = [
+            (SettingsField::LedBrightnessTop, "led_brightness_top"),
+            (
+                SettingsField::LedBrightnessUnderglow,
+                "led_brightness_underglow",
+            ),
+            (SettingsField::LedIdleTimeLimit, "led_idle_time_limit"),
+            (SettingsField::QukeysHoldTimeout, "qukeys_hold_timeout"),
+            (
+                SettingsField::QukeysOverlapThreshold,
+                "qukeys_overlap_threshold",
+            ),
+            (SettingsField::SuperkeysWaitFor, "superkeys_wait_for"),
+            (SettingsField::SuperkeysTimeout, "superkeys_timeout"),
+            (SettingsField::SuperkeysRepeat, "superkeys_repeat"),
+            (SettingsField::SuperkeysHoldStart, "superkeys_hold_start"),
+            (SettingsField::SuperkeysOverlap, "superkeys_overlap"),
+            (SettingsField::MouseSpeed, "mouse_speed"),
+            (SettingsField::MouseDelay, "mouse_delay"),
+            (
+                SettingsField::MouseAccelerationSpeed,
+                "mouse_acceleration_speed",
+            ),
+            (
+                SettingsField::MouseAccelerationDelay,
+                "mouse_acceleration_delay",
+            ),
+            (SettingsField::MouseWheelSpeed, "mouse_wheel_speed"),
+            (SettingsField::MouseWheelDelay, "mouse_wheel_delay"),
+            (SettingsField::MouseSpeedLimit, "mouse_speed_limit"),
+        ];
+
+        // None of these match a default, so every reset shows up as a difference.
+        let mut baseline = sample_settings();
+        baseline.led_brightness_top = 1;
+        baseline.led_brightness_underglow = Some(1);
+        baseline.led_idle_time_limit = Duration::from_secs(1);
+        baseline.qukeys_hold_timeout = Duration::from_millis(1);
+        baseline.qukeys_overlap_threshold = Duration::from_millis(1);
+        baseline.superkeys_wait_for = Duration::from_millis(1);
+        baseline.superkeys_timeout = Duration::from_millis(1);
+        baseline.superkeys_repeat = Duration::from_millis(1);
+        baseline.superkeys_hold_start = Duration::from_millis(1);
+        baseline.superkeys_overlap = 1;
+        baseline.mouse_speed = 1;
+        baseline.mouse_delay = Duration::from_millis(1);
+        baseline.mouse_acceleration_speed = 1;
+        baseline.mouse_acceleration_delay = Duration::from_millis(1);
+        baseline.mouse_wheel_speed = 1;
+        baseline.mouse_wheel_delay = Duration::from_millis(1);
+        baseline.mouse_speed_limit = 1;
+
+        for (field, name) in fields {
+            let reset = field
+                .reset(SettingsBuilder::new(baseline.clone()), Product::Defy)
+                .build();
+
+            assert_eq!(differing_fields(&baseline, &reset), vec![name]);
+        }
+    }
+
+    #[test]
+    fn identical_settings_have_no_differences() {
+        let settings = sample_settings();
+        assert!(differing_fields(&settings, &settings.clone()).is_empty());
+    }
+
+    #[test]
+    fn differences_include_maps_and_colors() {
+        let a = sample_settings();
+        let mut b = a.clone();
+        b.keymap_custom[0] = 0;
+        b.led_theme[0].g = 255;
+        b.palette_rgbw = None;
+        b.macros_map.push(1);
+        b.mouse_speed += 1;
+
+        assert_eq!(
+            differing_fields(&a, &b),
+            vec![
+                "keymap_custom",
+                "led_theme",
+                "palette_rgbw",
+                "macros_map",
+                "mouse_speed"
+            ]
+        );
+    }
+}
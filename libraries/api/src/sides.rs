@@ -0,0 +1,84 @@
+use crate::command::command_response;
+use crate::features::supports;
+use anyhow::{Context, Result};
+use dygma_focus::Focus;
+use log::warn;
+
+fn side_value<'a>(response: &'a str, side: &str) -> Option<&'a str> {
+    response.lines().map(str::trim).find_map(|line| {
+        let prefix = line.get(..side.len())?;
+        prefix.eq_ignore_ascii_case(side).then(|| {
+            line[side.len()..]
+                .trim_start_matches([':', ' ', '\t'])
+                .trim()
+        })
+    })
+}
+
+/// Reads the keyscanner firmware versions of the left and right halves from `hardware.side_ver`.
+#[tracing::instrument(skip(focus))]
+pub async fn side_versions(focus: &mut Focus) -> Result<(String, String)> {
+    let response = command_response(focus, "hardware.side_ver").await?;
+
+    let left = side_value(&response, "left").context("Left side version missing")?;
+    let right = side_value(&response, "right").context("Right side version missing")?;
+
+    Ok((left.to_string(), right.to_string()))
+}
+
+/// Whether both halves run the same keyscanner firmware, warns when they differ.
+pub async fn sides_match(focus: &mut Focus) -> Result<bool> {
+    let (left, right) = side_versions(focus).await?;
+
+    if left != right {
+        warn!("Side firmware mismatch, left: {}, right: {}", left, right);
+        return Ok(false);
+    }
+
+    Ok(true)
+}
+
+/// A disconnected half reports an empty or zero version.
+fn is_connected_version(version: Option<&str>) -> bool {
+    version
+        .is_some_and(|version| !version.is_empty() && !version.trim_matches(['0', '.']).is_empty())
+}
+
+/// Whether the left and right halves are connected, without entering upgrade mode.
+///
+/// Returns `None` for devices that aren't split, as they have no `hardware.side_ver`.
+#[tracing::instrument(skip(focus))]
+pub async fn sides_connected(focus: &mut Focus) -> Result<Option<(bool, bool)>> {
+    if !supports(focus, "hardware.side_ver").await? {
+        return Ok(None);
+    }
+
+    let response = command_response(focus, "hardware.side_ver").await?;
+
+    Ok(Some((
+        is_connected_version(side_value(&response, "left")),
+        is_connected_version(side_value(&response, "right")),
+    )))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn side_value_matches_case_insensitively() {
+        let response = "Left: 5\r\nRIGHT: 6";
+
+        assert_eq!(side_value(response, "left"), Some("5"));
+        assert_eq!(side_value(response, "right"), Some("6"));
+    }
+
+    #[test]
+    fn side_value_handles_short_and_non_ascii_lines() {
+        let response = "a\u{e9}\u{e9}\nright: 1";
+
+        assert_eq!(side_value(response, "left"), None);
+        assert_eq!(side_value(response, "right"), Some("1"));
+        assert_eq!(side_value("ri", "right"), None);
+    }
+}
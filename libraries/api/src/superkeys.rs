@@ -0,0 +1,142 @@
+use crate::settings::SettingsBuilder;
+use anyhow::{bail, Result};
+use dygma_focus::prelude::*;
+use std::time::Duration;
+
+/// Highest overlap percentage the firmware accepts.
+pub const MAX_SUPERKEYS_OVERLAP: u8 = 80;
+/// Superkey timings are stored as 16-bit millisecond values.
+pub const MAX_SUPERKEYS_DURATION: Duration = Duration::from_millis(u16::MAX as u64);
+
+/// Superkey timing settings, read and written as a unit.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SuperkeysConfig {
+    pub wait_for: Duration,
+    pub timeout: Duration,
+    pub repeat: Duration,
+    pub hold_start: Duration,
+    /// Percentage of overlap with the next key before it counts as a hold.
+    pub overlap: u8,
+}
+
+impl SuperkeysConfig {
+    pub fn validate(&self) -> Result<()> {
+        if self.overlap > MAX_SUPERKEYS_OVERLAP {
+            bail!(
+                "Superkeys overlap {} exceeds the maximum of {}",
+                self.overlap,
+                MAX_SUPERKEYS_OVERLAP
+            );
+        }
+
+        for (name, duration) in [
+            ("wait for", self.wait_for),
+            ("timeout", self.timeout),
+            ("repeat", self.repeat),
+            ("hold start", self.hold_start),
+        ] {
+            if duration > MAX_SUPERKEYS_DURATION {
+                bail!(
+                    "Superkeys {} of {}ms exceeds the maximum of {}ms",
+                    name,
+                    duration.as_millis(),
+                    MAX_SUPERKEYS_DURATION.as_millis()
+                );
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Overrides the superkey fields of the builder, for writing alongside other settings.
+    pub fn apply_to(&self, builder: SettingsBuilder) -> SettingsBuilder {
+        builder
+            .superkeys_wait_for(self.wait_for)
+            .superkeys_timeout(self.timeout)
+            .superkeys_repeat(self.repeat)
+            .superkeys_hold_start(self.hold_start)
+            .superkeys_overlap(self.overlap)
+    }
+}
+
+impl From<&Settings> for SuperkeysConfig {
+    fn from(settings: &Settings) -> Self {
+        Self {
+            wait_for: settings.superkeys_wait_for,
+            timeout: settings.superkeys_timeout,
+            repeat: settings.superkeys_repeat,
+            hold_start: settings.superkeys_hold_start,
+            overlap: settings.superkeys_overlap,
+        }
+    }
+}
+
+#[tracing::instrument(skip(focus))]
+pub async fn superkeys_config_get(focus: &mut Focus) -> Result<SuperkeysConfig> {
+    Ok(SuperkeysConfig {
+        wait_for: focus.superkeys_wait_for_get().await?,
+        timeout: focus.superkeys_timeout_get().await?,
+        repeat: focus.superkeys_repeat_get().await?,
+        hold_start: focus.superkeys_hold_start_get().await?,
+        overlap: focus.superkeys_overlap_get().await?,
+    })
+}
+
+/// Validates every field before writing any of them.
+#[tracing::instrument(skip(focus))]
+pub async fn superkeys_config_set(focus: &mut Focus, config: &SuperkeysConfig) -> Result<()> {
+    config.validate()?;
+
+    focus.superkeys_wait_for_set(config.wait_for).await?;
+    focus.superkeys_timeout_set(config.timeout).await?;
+    focus.superkeys_repeat_set(config.repeat).await?;
+    focus.superkeys_hold_start_set(config.hold_start).await?;
+    focus.superkeys_overlap_set(config.overlap).await?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::settings::tests::sample_settings;
+
+    fn config(overlap: u8) -> SuperkeysConfig {
+        SuperkeysConfig {
+            wait_for: Duration::from_millis(500),
+            timeout: Duration::from_millis(250),
+            repeat: Duration::from_millis(20),
+            hold_start: Duration::from_millis(200),
+            overlap,
+        }
+    }
+
+    #[test]
+    fn overlap_up_to_the_maximum_is_accepted() {
+        assert!(config(80).validate().is_ok());
+        assert!(config(81).validate().is_err());
+    }
+
+    #[test]
+    fn durations_over_16_bits_are_rejected() {
+        let config = SuperkeysConfig {
+            timeout: Duration::from_millis(u16::MAX as u64 + 1),
+            ..config(20)
+        };
+
+        assert!(config.validate().is_err());
+    }
+
+    #[test]
+    fn round_trips_through_settings() {
+        let settings = sample_settings();
+        let config = SuperkeysConfig {
+            wait_for: Duration::from_millis(700),
+            ..SuperkeysConfig::from(&settings)
+        };
+
+        let updated = config.apply_to(SettingsBuilder::new(settings)).build();
+
+        assert_eq!(SuperkeysConfig::from(&updated), config);
+    }
+}
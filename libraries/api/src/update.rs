@@ -0,0 +1,188 @@
+use crate::devices::{probe, Connection, DeviceState};
+use crate::firmware_downloader::{
+    download_firmware, github_read, Collected, Ctx, Firmware, FirmwareVersion,
+};
+use crate::flash::devices::defy::nrf52833_flasher::Flasher;
+use crate::flash::devices::defy::side_flasher::SideFlasher;
+use crate::flash::{check_firmware_target, flash_warnings, FlashWarning};
+use crate::sides::sides_match;
+use anyhow::{bail, Context, Result};
+use dygma_focus::hardware::{Device, Product};
+use log::info;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum UpdateProgress {
+    Checking,
+    Downloading { version: String },
+    FlashingSides,
+    FlashingNeuron,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum UpdateOutcome {
+    AlreadyUpToDate {
+        version: String,
+    },
+    /// The device runs a newer version than the latest release on the selected channel.
+    NewerInstalled {
+        installed: String,
+        latest: String,
+    },
+    /// Flashing the latest release needs the user's confirmation, nothing was flashed.
+    NeedsConfirmation {
+        target: String,
+        warnings: Vec<FlashWarning>,
+    },
+    Updated {
+        from: String,
+        to: String,
+    },
+}
+
+/// Flashes the latest matching firmware if the device is running an older version.
+///
+/// Devices in bootloader mode are rejected, since their installed version is unknown and the
+/// flasher only accepts devices running the application firmware. Never downgrades, and
+/// returns [`UpdateOutcome::NeedsConfirmation`] instead of flashing when [`flash_warnings`]
+/// has anything to say.
+#[tracing::instrument(skip(device, progress))]
+pub async fn update_device<F>(
+    device: &Device,
+    allow_beta: bool,
+    progress: F,
+) -> Result<UpdateOutcome>
+where
+    F: Fn(UpdateProgress),
+{
+    progress(UpdateProgress::Checking);
+
+    if device.hardware.info.product != Product::Defy {
+        bail!(
+            "Flashing {} is not supported yet",
+            device.hardware.info.display_name
+        );
+    }
+
+    let current = match probe(device).await? {
+        DeviceState::Application { version } => version,
+        DeviceState::Bootloader => bail!(
+            "{} is in bootloader mode, restart it into the application firmware to update",
+            device.hardware.info.display_name
+        ),
+    };
+
+    let info = github_read(Ctx {
+        device: device.hardware,
+        collected: Collected {
+            version: current.clone(),
+        },
+        allow_beta,
+    })
+    .await?;
+
+    let latest = info.firmwares[0].clone();
+
+    if info.is_updated {
+        info!("Already up to date: {}", latest.version);
+        return Ok(UpdateOutcome::AlreadyUpToDate {
+            version: latest.version,
+        });
+    }
+
+    if !is_outdated(&current, &latest.version)? {
+        info!(
+            "Installed {} is newer than the latest release {}",
+            current, latest.version
+        );
+        return Ok(UpdateOutcome::NewerInstalled {
+            installed: current,
+            latest: latest.version,
+        });
+    }
+
+    let warnings = flash_warnings(&current, &latest);
+    if !warnings.is_empty() {
+        return Ok(UpdateOutcome::NeedsConfirmation {
+            target: latest.version,
+            warnings,
+        });
+    }
+
+    progress(UpdateProgress::Downloading {
+        version: latest.version.clone(),
+    });
+    let firmware = download_firmware(&device.hardware, &latest).await?;
+    check_firmware_target(&device.hardware, &latest, &firmware)?;
+
+    flash_firmware(device, &firmware, &progress).await?;
+
+    info!("Updated to {}", latest.version);
+
+    Ok(UpdateOutcome::Updated {
+        from: current,
+        to: latest.version,
+    })
+}
+
+/// Whether the latest release is newer than the installed firmware.
+fn is_outdated(current: &str, latest: &str) -> Result<bool> {
+    let current = FirmwareVersion::parse(current)
+        .with_context(|| format!("Can't compare installed firmware {}", current))?;
+    let latest = FirmwareVersion::parse(latest)
+        .with_context(|| format!("Can't compare release {}", latest))?;
+
+    Ok(current.semver < latest.semver)
+}
+
+async fn flash_firmware<F>(device: &Device, firmware: &Firmware, progress: &F) -> Result<()>
+where
+    F: Fn(UpdateProgress),
+{
+    // Validate the neuron image before touching the sides, so a bad download can't leave
+    // the sides updated and the neuron on the old firmware.
+    let Some(hex_raw) = &firmware.firmware.hex_raw else {
+        bail!(
+            "Firmware {} is not an Intel HEX file",
+            firmware.firmware.name
+        );
+    };
+    let decoded_hexes = Flasher::parse(hex_raw)?;
+
+    if let Some(sides) = &firmware.sides {
+        progress(UpdateProgress::FlashingSides);
+        SideFlasher::flash_side(device, sides).await?;
+
+        let mut connection = Connection::open(device)?;
+        if !sides_match(connection.focus()?).await? {
+            bail!("Sides are running different firmware after flashing");
+        }
+    }
+
+    progress(UpdateProgress::FlashingNeuron);
+    Flasher::new(device)?.flash_parsed(&decoded_hexes).await?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn older_install_is_outdated() {
+        assert!(is_outdated("v1.1.0", "v1.2.0").unwrap());
+        assert!(is_outdated("1.2.0-beta.1", "1.2.0").unwrap());
+    }
+
+    #[test]
+    fn same_or_newer_install_is_not_outdated() {
+        assert!(!is_outdated("v1.2.0", "v1.2.0").unwrap());
+        assert!(!is_outdated("v1.3.0", "v1.2.0").unwrap());
+        assert!(!is_outdated("v1.3.0-beta.1", "v1.2.0").unwrap());
+    }
+
+    #[test]
+    fn unparsable_versions_are_refused() {
+        assert!(is_outdated("garbage", "v1.2.0").is_err());
+    }
+}
@@ -0,0 +1,260 @@
+use crate::command::command_response;
+use crate::features::supports;
+use anyhow::{bail, Context, Result};
+use dygma_focus::prelude::*;
+use futures::stream::{self, Stream};
+use log::{debug, info};
+use std::collections::VecDeque;
+use std::time::{Duration, Instant};
+
+const PAIRING_POLL_INTERVAL: Duration = Duration::from_millis(500);
+const BLUETOOTH_CURRENT_HOST: &str = "wireless.bluetooth.currentHost";
+const BATTERY_LEVEL_LEFT: &str = "wireless.battery.left.level";
+/// Percentage a side has to recover above the threshold before it can alert again.
+const BATTERY_HYSTERESIS: u8 = 5;
+
+/// Triggers RF sync pairing between the Neuron and the keyboard halves.
+///
+/// `Focus::wireless_rf_sync_pairing` sends `wireless.rf.syncPairing` and returns the
+/// acknowledgement of the request, not whether pairing has finished.
+/// Use [`sync_pairing_wait`] afterwards to wait for the halves to come back.
+///
+/// Re-pairing a Defy half:
+/// 1. Connect the Neuron via USB.
+/// 2. Call [`sync_pairing_start`].
+/// 3. Connect each half to the Neuron with the cable, one at a time, while pairing is active.
+/// 4. Call [`sync_pairing_wait`] until both halves report as connected.
+#[tracing::instrument(skip(focus))]
+pub async fn sync_pairing_start(focus: &mut Focus) -> Result<()> {
+    info!("Starting RF sync pairing");
+
+    if !focus.wireless_rf_sync_pairing().await? {
+        bail!("Device rejected the sync pairing request");
+    }
+
+    Ok(())
+}
+
+/// Polls both halves until they are connected again or the timeout elapses.
+///
+/// Returns `true` once both sides report as connected, `false` on timeout.
+#[tracing::instrument(skip(focus))]
+pub async fn sync_pairing_wait(focus: &mut Focus, timeout: Duration) -> Result<bool> {
+    let start = Instant::now();
+
+    while start.elapsed() < timeout {
+        let left = focus.upgrade_keyscanner_is_connected(Side::Left).await?;
+        let right = focus.upgrade_keyscanner_is_connected(Side::Right).await?;
+        debug!("Pairing state: left {}, right {}", left, right);
+
+        if left && right {
+            info!("Sync pairing complete");
+            return Ok(true);
+        }
+
+        tokio::time::sleep(PAIRING_POLL_INTERVAL).await;
+    }
+
+    Ok(false)
+}
+
+/// Bluetooth host the keyboard is currently connected to, `None` when the firmware can't report it.
+///
+/// None of the `help` tables known so far list `wireless.bluetooth.currentHost`, their Bluetooth
+/// commands are `macs`, `peerIds`, `remove`, `deviceName` and `list`. It is feature detected so
+/// firmware that adds it works without changes here.
+#[tracing::instrument(skip(focus))]
+pub async fn bluetooth_current_host(focus: &mut Focus) -> Result<Option<u8>> {
+    if !supports(focus, BLUETOOTH_CURRENT_HOST).await? {
+        return Ok(None);
+    }
+
+    let response = command_response(focus, BLUETOOTH_CURRENT_HOST).await?;
+
+    Ok(Some(parse_host_id(&response)?))
+}
+
+/// Switches the keyboard to another paired Bluetooth host.
+///
+/// Fails when the firmware doesn't support `wireless.bluetooth.currentHost`, see
+/// [`bluetooth_current_host`].
+#[tracing::instrument(skip(focus))]
+pub async fn bluetooth_switch_host(focus: &mut Focus, id: u8) -> Result<()> {
+    if !supports(focus, BLUETOOTH_CURRENT_HOST).await? {
+        bail!("Firmware does not support switching Bluetooth hosts");
+    }
+
+    let response = command_response(focus, &format!("{} {}", BLUETOOTH_CURRENT_HOST, id)).await?;
+    if !response.is_empty() {
+        bail!(
+            "Device rejected switching to Bluetooth host {}: {}",
+            id,
+            response
+        );
+    }
+
+    Ok(())
+}
+
+fn parse_host_id(response: &str) -> Result<u8> {
+    response
+        .trim()
+        .parse()
+        .with_context(|| format!("Invalid {} response: {}", BLUETOOTH_CURRENT_HOST, response))
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BatteryInfo {
+    pub left: u8,
+    pub right: u8,
+}
+
+/// Reads the battery level of both halves, in percent.
+pub async fn battery_info(focus: &mut Focus) -> Result<BatteryInfo> {
+    Ok(BatteryInfo {
+        left: focus.wireless_battery_level_left_get().await?,
+        right: focus.wireless_battery_level_right_get().await?,
+    })
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct BatteryAlert {
+    pub side: Side,
+    pub level: u8,
+}
+
+struct BatteryWatch<'a> {
+    focus: &'a mut Focus,
+    threshold: u8,
+    interval: Duration,
+    low: [bool; 2],
+    pending: VecDeque<BatteryAlert>,
+    polled: bool,
+}
+
+/// Polls the battery levels and yields an alert when a side drops below the threshold.
+///
+/// A side alerts again only after recovering above the threshold by the hysteresis margin,
+/// so a level hovering around the threshold doesn't flap.
+pub fn watch_battery(
+    focus: &mut Focus,
+    threshold: u8,
+    interval: Duration,
+) -> impl Stream<Item = Result<BatteryAlert>> + '_ {
+    let watch = BatteryWatch {
+        focus,
+        threshold,
+        interval,
+        low: [false; 2],
+        pending: VecDeque::new(),
+        polled: false,
+    };
+
+    stream::unfold(watch, |mut watch| async move {
+        loop {
+            if let Some(alert) = watch.pending.pop_front() {
+                return Some((Ok(alert), watch));
+            }
+
+            if watch.polled {
+                tokio::time::sleep(watch.interval).await;
+            }
+            watch.polled = true;
+
+            let info = match battery_info(watch.focus).await {
+                Ok(info) => info,
+                Err(e) => return Some((Err(e), watch)),
+            };
+
+            let sides = [(Side::Left, info.left), (Side::Right, info.right)];
+            for (low, (side, level)) in watch.low.iter_mut().zip(sides) {
+                if !*low && level < watch.threshold {
+                    *low = true;
+                    watch.pending.push_back(BatteryAlert { side, level });
+                } else if *low && level >= watch.threshold.saturating_add(BATTERY_HYSTERESIS) {
+                    *low = false;
+                }
+            }
+        }
+    })
+}
+
+/// How the keyboard is linked to the host.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ConnectionMode {
+    Wired,
+    Wireless,
+}
+
+/// Whether the halves are currently linked to the Neuron by cable or over RF.
+///
+/// Firmware without the wireless battery commands is wired-only, such as the Raise and the
+/// Defy Wired. Otherwise the halves are asked whether they are reachable over the cable
+/// with `upgrade.keyscanner.isConnected`, the same check [`sync_pairing_wait`] polls while
+/// the halves are cabled for pairing.
+#[tracing::instrument(skip(focus))]
+pub async fn connection_mode(focus: &mut Focus) -> Result<ConnectionMode> {
+    if !supports(focus, BATTERY_LEVEL_LEFT).await? {
+        return Ok(ConnectionMode::Wired);
+    }
+
+    let left = focus.upgrade_keyscanner_is_connected(Side::Left).await?;
+    let right = focus.upgrade_keyscanner_is_connected(Side::Right).await?;
+    debug!("Cable connection: left {}, right {}", left, right);
+
+    Ok(decide_connection_mode(true, left, right))
+}
+
+/// Wired only when every half is on the cable, a single half on RF needs the wireless settings.
+fn decide_connection_mode(
+    wireless_supported: bool,
+    left_wired: bool,
+    right_wired: bool,
+) -> ConnectionMode {
+    if !wireless_supported || (left_wired && right_wired) {
+        ConnectionMode::Wired
+    } else {
+        ConnectionMode::Wireless
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_current_host_id() {
+        assert_eq!(parse_host_id("2").unwrap(), 2);
+        assert_eq!(parse_host_id(" 0\r\n").unwrap(), 0);
+        assert!(parse_host_id("").is_err());
+        assert!(parse_host_id("host").is_err());
+    }
+
+    #[test]
+    fn wired_only_firmware_is_wired() {
+        assert_eq!(
+            decide_connection_mode(false, false, false),
+            ConnectionMode::Wired
+        );
+    }
+
+    #[test]
+    fn both_halves_on_the_cable_is_wired() {
+        assert_eq!(
+            decide_connection_mode(true, true, true),
+            ConnectionMode::Wired
+        );
+    }
+
+    #[test]
+    fn any_half_on_rf_is_wireless() {
+        assert_eq!(
+            decide_connection_mode(true, true, false),
+            ConnectionMode::Wireless
+        );
+        assert_eq!(
+            decide_connection_mode(true, false, false),
+            ConnectionMode::Wireless
+        );
+    }
+}